@@ -126,8 +126,8 @@ pub fn version_lte(v1: &str, v2: &str) -> bool {
     v1 <= v2
 }
 
-/// Delete migration files at or before the baseline version.
-/// Returns the list of deleted file paths.
+/// Delete migration files and directories at or before the baseline version, along with
+/// their down scripts and asset directories. Returns the list of deleted migrations.
 pub fn delete_baselined_migrations(
     baseline_version: &str,
     available: &[crate::Migration],
@@ -135,7 +135,19 @@ pub fn delete_baselined_migrations(
     let mut deleted = Vec::new();
 
     for migration in available {
-        if version_lte(&migration.version, baseline_version) && migration.file_path.exists() {
+        if !version_lte(&migration.version, baseline_version) {
+            continue;
+        }
+
+        if let Some(dir) = migration.dir.as_ref().filter(|d| d.exists()) {
+            fs::remove_dir_all(dir).with_context(|| {
+                format!("Failed to delete migration directory: {}", dir.display())
+            })?;
+            deleted.push(dir.display().to_string());
+            continue;
+        }
+
+        if migration.file_path.exists() {
             fs::remove_file(&migration.file_path).with_context(|| {
                 format!(
                     "Failed to delete migration file: {}",
@@ -143,6 +155,24 @@ pub fn delete_baselined_migrations(
                 )
             })?;
             deleted.push(migration.file_path.display().to_string());
+
+            if let Some(down_path) = migration.down_path.as_ref().filter(|p| p.exists()) {
+                fs::remove_file(down_path).with_context(|| {
+                    format!("Failed to delete down script: {}", down_path.display())
+                })?;
+            }
+
+            // Left behind, the asset directory would look like a directory migration
+            if let Some(assets) = migration
+                .file_path
+                .parent()
+                .map(|parent| parent.join(&migration.id))
+                .filter(|dir| dir.is_dir())
+            {
+                fs::remove_dir_all(&assets).with_context(|| {
+                    format!("Failed to delete asset directory: {}", assets.display())
+                })?;
+            }
         }
     }
 
@@ -243,6 +273,7 @@ summary: |
             id: "1f700-first".to_string(),
             version: "1f700".to_string(),
             file_path: PathBuf::from("1f700-first.sh"),
+            ..Default::default()
         }];
         let applied = vec![];
 
@@ -261,16 +292,19 @@ summary: |
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: PathBuf::from("1f700-first.sh"),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: PathBuf::from("1f710-second.sh"),
+                ..Default::default()
             },
         ];
         let applied = vec![AppliedMigration {
             id: "1f710-second".to_string(),
             applied_at: Utc::now(),
+            ..Default::default()
         }];
 
         // Try to baseline at 1f710, but 1f700 hasn't been applied
@@ -289,21 +323,25 @@ summary: |
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: PathBuf::from("1f700-first.sh"),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: PathBuf::from("1f710-second.sh"),
+                ..Default::default()
             },
         ];
         let applied = vec![
             AppliedMigration {
                 id: "1f700-first".to_string(),
                 applied_at: Utc::now(),
+                ..Default::default()
             },
             AppliedMigration {
                 id: "1f710-second".to_string(),
                 applied_at: Utc::now(),
+                ..Default::default()
             },
         ];
 
@@ -325,21 +363,25 @@ summary: |
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: PathBuf::from("1f700-first.sh"),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: PathBuf::from("1f710-second.sh"),
+                ..Default::default()
             },
         ];
         let applied = vec![
             AppliedMigration {
                 id: "1f700-first".to_string(),
                 applied_at: Utc::now(),
+                ..Default::default()
             },
             AppliedMigration {
                 id: "1f710-second".to_string(),
                 applied_at: Utc::now(),
+                ..Default::default()
             },
         ];
 
@@ -1,15 +1,18 @@
 use anyhow::Result;
 use chrono::Utc;
 use std::path::Path;
+use std::time::Duration;
 
 use crate::baseline::{
     delete_baselined_migrations, read_baseline, validate_baseline, write_baseline, Baseline,
 };
 
 use crate::loader::discover_migrations;
+use crate::lock::Lock;
 use crate::state::read_history;
 
-/// Create a baseline at the specified version
+/// Create a baseline at the specified version.
+/// `wait` is how long to wait for another run holding the migrations lock.
 pub fn run(
     project_root: &Path,
     migrations_dir: &Path,
@@ -17,6 +20,7 @@ pub fn run(
     summary: Option<&str>,
     dry_run: bool,
     keep: bool,
+    wait: Option<Duration>,
 ) -> Result<()> {
     let migrations_path = if migrations_dir.is_absolute() {
         migrations_dir.to_path_buf()
@@ -32,6 +36,12 @@ pub fn run(
         return Ok(());
     }
 
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(Lock::acquire(&migrations_path, wait)?)
+    };
+
     let available = discover_migrations(&migrations_path)?;
     let applied = read_history(&migrations_path)?;
     let existing_baseline = read_baseline(&migrations_path)?;
@@ -63,7 +73,8 @@ pub fn run(
             if dry_run { "Would delete" } else { "Deleting" }
         );
         for migration in &to_delete {
-            println!("  - {}", migration.id);
+            let suffix = if migration.dir.is_some() { "/" } else { "" };
+            println!("  - {}{}", migration.id, suffix);
         }
         println!();
     } else if keep {
@@ -1,4 +1,6 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::collections::BTreeMap;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::Path;
@@ -6,83 +8,161 @@ use std::path::Path;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
-use crate::loader::discover_migrations;
-use crate::templates::{get_template, list_templates};
+use crate::executor::runs_in_process;
+use crate::host;
+use crate::loader::extract_version;
+use crate::render::render;
+use crate::templates::available_templates;
 use crate::version::generate_version;
 
-/// Create a new migration file
-pub fn run(
-    project_root: &Path,
-    migrations_dir: &Path,
-    name: Option<&str>,
-    template_name: &str,
-    description: Option<&str>,
-    should_list_templates: bool,
-) -> Result<()> {
+/// Options for creating a migration
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    /// Migration name (e.g., "add-config")
+    pub name: Option<String>,
+    /// Name of the template to render
+    pub template: String,
+    /// Migration description
+    pub description: Option<String>,
+    /// Template variables, taking precedence over the built-in ones
+    pub vars: Vec<(String, String)>,
+    /// Create a directory migration with a `run` entry point instead of a single file
+    pub dir: bool,
+    /// List the available templates instead of creating a migration
+    pub list_templates: bool,
+}
+
+/// Create a new migration from a template.
+/// Templates can use `{{ID}}`, `{{VERSION}}`, `{{NAME}}`, `{{DATE}}`, `{{AUTHOR}}`,
+/// `{{DESCRIPTION}}` and any `vars`, which take precedence.
+/// With `dir`, the template becomes the `run.<ext>` entry point of a new `<id>/` directory.
+pub fn run(project_root: &Path, migrations_dir: &Path, options: &CreateOptions) -> Result<()> {
+    let CreateOptions {
+        ref name,
+        template: ref template_name,
+        ref description,
+        ref vars,
+        dir,
+        list_templates: should_list_templates,
+    } = *options;
+
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    let templates = available_templates(&migrations_path)?;
+
     // Handle --list-templates flag
     if should_list_templates {
+        let width = templates.iter().map(|t| t.name.len()).max().unwrap_or(0);
+        let ext_width = templates
+            .iter()
+            .map(|t| t.extension.len())
+            .max()
+            .unwrap_or(0);
         println!("Available templates:");
-        for template in list_templates() {
-            println!("  {}", template);
+        for template in &templates {
+            println!(
+                "  {:<width$}  {:<ext_width$}  {}",
+                template.name,
+                template.extension,
+                template.source,
+                width = width,
+                ext_width = ext_width
+            );
         }
         return Ok(());
     }
 
     // Name is required when not listing templates
-    let name = match name {
+    let name = match name.as_deref() {
         Some(n) => n,
         None => bail!("Migration name is required. Usage: migrate create <name>"),
     };
 
     // Validate template
-    let template = match get_template(template_name) {
+    let template = match templates.iter().find(|t| t.name == *template_name) {
         Some(t) => t,
         None => {
             bail!(
                 "Unknown template '{}'. Available: {}",
                 template_name,
-                list_templates().collect::<Vec<_>>().join(", ")
+                templates
+                    .iter()
+                    .map(|t| t.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
             );
         }
     };
 
-    let migrations_path = if migrations_dir.is_absolute() {
-        migrations_dir.to_path_buf()
-    } else {
-        project_root.join(migrations_dir)
-    };
-
     // Create migrations directory if it doesn't exist
     fs::create_dir_all(&migrations_path)?;
 
     // Generate version from current time
     let version = generate_version();
 
-    // Check for version collision with existing migrations
-    let existing = discover_migrations(&migrations_path).unwrap_or_default();
-    if existing.iter().any(|m| m.version == version) {
+    // Check for version collision with anything already using the version, scanning
+    // names only so that a broken migration can't switch the check off
+    let mut taken = false;
+    for entry in fs::read_dir(&migrations_path)? {
+        let name = entry?.file_name();
+        taken |= extract_version(&name.to_string_lossy()).as_deref() == Some(version.as_str());
+    }
+    if taken {
         bail!(
             "A migration with version {} already exists. Wait a few minutes or use a different time slot.",
             version
         );
     }
 
-    // Build filename
-    let filename = format!("{}-{}{}", version, name, template.extension);
-    let file_path = migrations_path.join(&filename);
+    // Build the migration's path: a file, or a directory holding the entry point
+    let id = format!("{}-{}", version, name);
+    let (migration_path, file_path) = if dir {
+        let dir_path = migrations_path.join(&id);
+        let entry_point = dir_path.join(format!("run{}", template.extension));
+        (dir_path, entry_point)
+    } else {
+        let file_path = migrations_path.join(format!("{}{}", id, template.extension));
+        (file_path.clone(), file_path)
+    };
 
-    // Check if file already exists
-    if file_path.exists() {
-        bail!("Migration file already exists: {}", file_path.display());
+    // Check if the migration already exists
+    if migration_path.exists() {
+        bail!("Migration already exists: {}", migration_path.display());
     }
 
-    // Prepare template content
-    let description_text = description.unwrap_or("TODO: Add description");
-    let content = template
-        .content
-        .replace("{{DESCRIPTION}}", description_text);
+    // Render template content
+    let mut variables = BTreeMap::from([
+        ("ID".to_string(), id),
+        ("VERSION".to_string(), version.clone()),
+        ("NAME".to_string(), name.to_string()),
+        (
+            "DATE".to_string(),
+            Utc::now().format("%Y-%m-%d").to_string(),
+        ),
+        (
+            "AUTHOR".to_string(),
+            host::git_author(project_root).unwrap_or_default(),
+        ),
+        (
+            "DESCRIPTION".to_string(),
+            description
+                .as_deref()
+                .unwrap_or("TODO: Add description")
+                .to_string(),
+        ),
+    ]);
+    variables.extend(vars.iter().cloned());
+    let content = render(&template.content, &variables)
+        .with_context(|| format!("Failed to render template '{}'", template.name))?;
 
     // Write file
+    if dir {
+        fs::create_dir(&migration_path)?;
+    }
     let mut file = OpenOptions::new()
         .create(true)
         .write(true)
@@ -91,15 +171,20 @@ pub fn run(
 
     file.write_all(content.as_bytes())?;
 
-    // Make executable (chmod +x) - only on Unix
+    // Make executable (chmod +x) - only on Unix, and only for migrations that are executed
     #[cfg(unix)]
-    {
+    if !runs_in_process(&file_path) {
         let mut perms = fs::metadata(&file_path)?.permissions();
         perms.set_mode(0o755);
         fs::set_permissions(&file_path, perms)?;
     }
 
-    println!("Created migration: {}", file_path.display());
+    if dir {
+        println!("Created migration: {}/", migration_path.display());
+        println!("  Entry point: {}", file_path.display());
+    } else {
+        println!("Created migration: {}", file_path.display());
+    }
 
     Ok(())
 }
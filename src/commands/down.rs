@@ -0,0 +1,194 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::baseline::read_baseline;
+use crate::config::interpreter_for;
+use crate::executor::execute;
+use crate::loader::discover_migrations;
+use crate::lock::Lock;
+use crate::logs::{new_log_path, prune_logs};
+use crate::state::{read_history, remove_history};
+use crate::{Direction, ExecutionContext, Phase};
+
+/// Options for reverting migrations
+#[derive(Debug, Clone, Default)]
+pub struct DownOptions {
+    /// Revert all migrations applied after this version
+    pub to: Option<String>,
+    /// Number of migrations to revert (default: 1)
+    pub steps: Option<usize>,
+    /// Preview without reverting
+    pub dry_run: bool,
+    /// Timeout for down scripts whose migration doesn't set a `Timeout:` header
+    pub timeout: Option<Duration>,
+    /// Number of run logs kept per migration; 0 disables output capture
+    pub log_retention: usize,
+    /// How long to wait for another run holding the migrations lock
+    pub wait: Option<Duration>,
+    /// Extra environment variables passed to every migration
+    pub env: BTreeMap<String, String>,
+    /// Interpreter commands by migration file extension
+    pub interpreters: BTreeMap<String, String>,
+}
+
+/// Revert applied migrations by running their down scripts in reverse order.
+/// Reverts the most recent migration unless `to` or `steps` is given.
+/// Output is captured in run logs alongside those of the migration.
+pub fn run(project_root: &Path, migrations_dir: &Path, options: &DownOptions) -> Result<()> {
+    let DownOptions {
+        ref to,
+        steps,
+        dry_run,
+        timeout,
+        log_retention,
+        wait,
+        ref env,
+        ref interpreters,
+    } = *options;
+
+    let project_root = if project_root.is_absolute() {
+        project_root.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(project_root)
+    };
+
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    if !migrations_path.exists() {
+        println!(
+            "No migrations directory found at: {}",
+            migrations_path.display()
+        );
+        return Ok(());
+    }
+
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(Lock::acquire(&migrations_path, wait)?)
+    };
+
+    let available = discover_migrations(&migrations_path)?;
+    let applied = read_history(&migrations_path)?;
+    let baseline = read_baseline(&migrations_path)?;
+
+    // Most recently applied first
+    let to_revert: Vec<_> = match to {
+        Some(version) => applied
+            .iter()
+            .rev()
+            .filter(|a| a.id.get(..5).is_some_and(|v| v > version.as_str()))
+            .collect(),
+        None => applied.iter().rev().take(steps.unwrap_or(1)).collect(),
+    };
+
+    if to_revert.is_empty() {
+        println!("No migrations to revert.");
+        return Ok(());
+    }
+
+    // Validate everything up front so we never stop halfway for a missing down script
+    let mut migrations = Vec::new();
+    for entry in &to_revert {
+        if let Some(b) = &baseline {
+            if entry.id.get(..5).is_some_and(|v| v <= b.version.as_str()) {
+                bail!(
+                    "Cannot revert migration '{}': it is covered by the baseline at '{}'",
+                    entry.id,
+                    b.version
+                );
+            }
+        }
+
+        let Some(migration) = available.iter().find(|m| m.id == entry.id) else {
+            bail!(
+                "Cannot revert migration '{}': migration file not found",
+                entry.id
+            );
+        };
+
+        if !migration.is_reversible() {
+            bail!(
+                "Cannot revert migration '{}': no down script found",
+                entry.id
+            );
+        }
+
+        migrations.push(migration);
+    }
+
+    println!(
+        "{} {} migration(s)...",
+        if dry_run { "Would revert" } else { "Reverting" },
+        migrations.len()
+    );
+    println!();
+
+    for migration in migrations {
+        println!("← {}", migration.id);
+
+        if dry_run {
+            println!("  (dry run - skipped)");
+            continue;
+        }
+
+        let log_path = (log_retention > 0).then(|| new_log_path(&migrations_path, &migration.id));
+
+        let ctx = ExecutionContext {
+            project_root: project_root.clone(),
+            migrations_dir: migrations_path.clone(),
+            migration_id: migration.id.clone(),
+            dry_run,
+            direction: Direction::Down,
+            phase: Phase::Run,
+            timeout: migration.timeout.or(timeout),
+            log_path: log_path.clone(),
+            env: env.clone(),
+            interpreter: migration
+                .down_path
+                .as_deref()
+                .and_then(|path| interpreter_for(interpreters, path)),
+        };
+
+        let result = execute(migration, &ctx)?;
+        if log_path.is_some() {
+            prune_logs(&migrations_path, &migration.id, log_retention)?;
+        }
+
+        if result.success {
+            remove_history(&migrations_path, &[migration.id.as_str()])?;
+            println!("  ✓ reverted");
+        } else {
+            if result.timed_out {
+                println!("  ✗ timed out");
+            } else {
+                println!("  ✗ failed (exit code {})", result.exit_code);
+            }
+            if let Some(error) = &result.error {
+                println!("    {}", error);
+            }
+            if let Some(path) = &log_path {
+                println!("    Log: {}", path.display());
+            }
+            if result.timed_out {
+                bail!(result.error.unwrap_or_default());
+            }
+            bail!(
+                "Down script for {} failed with exit code {}",
+                migration.id,
+                result.exit_code
+            );
+        }
+    }
+
+    println!();
+    println!("All migrations reverted successfully.");
+
+    Ok(())
+}
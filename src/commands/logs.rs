@@ -0,0 +1,51 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::logs::{list_logs, logged_ids};
+
+/// Print the captured output of a migration run.
+/// `run` selects a run by number, oldest first; the latest run is shown by default.
+pub fn run(project_root: &Path, migrations_dir: &Path, id: &str, run: Option<usize>) -> Result<()> {
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    // Accept either a full ID or just its version, and only ever look up logged IDs
+    let ids = logged_ids(&migrations_path)?;
+    let Some(id) = ids
+        .iter()
+        .find(|logged| logged.as_str() == id || logged.get(..5) == Some(id))
+        .map(|logged| logged.as_str())
+    else {
+        bail!("Migration '{}' not found", id);
+    };
+
+    let logs = list_logs(&migrations_path, id)?;
+    if logs.is_empty() {
+        bail!("No logs recorded for migration '{}'", id);
+    }
+
+    let number = run.unwrap_or(logs.len());
+    if number == 0 || number > logs.len() {
+        bail!(
+            "Migration '{}' has {} logged run(s); run {} does not exist",
+            id,
+            logs.len(),
+            number
+        );
+    }
+
+    let path = &logs[number - 1];
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read log file: {}", path.display()))?;
+
+    println!("Migration: {} (run {} of {})", id, number, logs.len());
+    println!("Log: {}", path.display());
+    println!();
+    print!("{}", content);
+
+    Ok(())
+}
@@ -1,4 +1,10 @@
 pub mod baseline;
 pub mod create;
+pub mod down;
+pub mod logs;
+pub mod show;
 pub mod status;
+pub mod test;
+pub mod undo;
 pub mod up;
+pub mod verify;
@@ -0,0 +1,64 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use crate::journal::Journal;
+use crate::state::{find_applied, read_history};
+use crate::tree::{unified_diff, FileChange};
+
+/// Show the files changed by an applied migration, as a summary or unified diff
+pub fn run(project_root: &Path, migrations_dir: &Path, id: &str, diff: bool) -> Result<()> {
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    // Accept either a full ID or just its version, and only ever look up recorded IDs
+    let applied = read_history(&migrations_path)?;
+    let Some(id) = find_applied(&applied, id).map(|a| a.id.as_str()) else {
+        bail!("No applied migration '{}'", id);
+    };
+
+    let journal = Journal::open(&migrations_path);
+    let Some(changes) = journal.read_manifest(id)? else {
+        bail!("No change journal recorded for migration '{}'", id);
+    };
+
+    println!("Migration: {}", id);
+
+    if changes.is_empty() {
+        println!("No files changed.");
+        return Ok(());
+    }
+
+    if !diff {
+        println!("{} file(s) changed:", changes.len());
+        for change in &changes {
+            println!("  {}  {}", change.kind().symbol(), change.path.display());
+        }
+        return Ok(());
+    }
+
+    println!();
+    for change in &changes {
+        print!("{}", render_diff(&journal, change)?);
+    }
+
+    Ok(())
+}
+
+/// Render a unified diff for a single changed file using contents from the journal.
+fn render_diff(journal: &Journal, change: &FileChange) -> Result<String> {
+    let read = |hash: &Option<String>| -> Result<Vec<u8>> {
+        match hash {
+            Some(h) => journal.read_object(h),
+            None => Ok(Vec::new()),
+        }
+    };
+
+    Ok(unified_diff(
+        change,
+        &read(&change.old_hash)?,
+        &read(&change.new_hash)?,
+    ))
+}
@@ -3,10 +3,15 @@ use std::path::Path;
 
 use crate::baseline::read_baseline;
 use crate::loader::discover_migrations;
-use crate::state::{get_current_version, get_pending, get_target_version, read_history};
+use crate::selection::Selection;
+use crate::state::{
+    get_current_version, get_modified, get_out_of_order, get_pending, get_target_version,
+    read_history,
+};
 
-/// Show the status of all migrations
-pub fn run(project_root: &Path, migrations_dir: &Path) -> Result<()> {
+/// Show the status of all migrations.
+/// Pending migrations left out by the selection are marked as not selected.
+pub fn run(project_root: &Path, migrations_dir: &Path, selection: &Selection) -> Result<()> {
     let migrations_path = if migrations_dir.is_absolute() {
         migrations_dir.to_path_buf()
     } else {
@@ -25,6 +30,8 @@ pub fn run(project_root: &Path, migrations_dir: &Path) -> Result<()> {
     let applied = read_history(&migrations_path)?;
     let baseline = read_baseline(&migrations_path)?;
     let pending = get_pending(&available, &applied, baseline.as_ref());
+    let selected = selection.apply(&pending)?;
+    let modified = get_modified(&available, &applied)?;
 
     if available.is_empty() && baseline.is_none() {
         println!("No migrations found in: {}", migrations_path.display());
@@ -33,6 +40,7 @@ pub fn run(project_root: &Path, migrations_dir: &Path) -> Result<()> {
 
     let current_version = get_current_version(&available, &applied);
     let target_version = get_target_version(&available);
+    let out_of_order = get_out_of_order(&available, &applied, &pending);
 
     println!("Migration Status");
     println!("================");
@@ -62,9 +70,12 @@ pub fn run(project_root: &Path, migrations_dir: &Path) -> Result<()> {
         (None, Some(target)) => {
             println!("Version: (none) -> {} ({} pending)", target, pending.len());
         }
-        (Some(current), Some(target)) if current == target => {
+        (Some(current), Some(target)) if current == target && pending.is_empty() => {
             println!("Version: {} (up to date)", current);
         }
+        (Some(current), Some(target)) if current == target => {
+            println!("Version: {} ({} pending)", current, pending.len());
+        }
         (Some(current), Some(target)) => {
             println!(
                 "Version: {} -> {} ({} pending)",
@@ -92,34 +103,102 @@ pub fn run(project_root: &Path, migrations_dir: &Path) -> Result<()> {
                 .as_ref()
                 .is_some_and(|b| extract_version(&migration.id) <= Some(b.version.clone()));
 
+            let is_reversible = available
+                .iter()
+                .any(|m| m.id == migration.id && m.is_reversible());
+
+            let is_modified = modified.iter().any(|m| m.id == migration.id);
+
+            let mut notes = Vec::new();
             if is_baselined {
-                println!(
-                    "  + {}  {}  (baseline)",
-                    migration.id,
-                    migration.applied_at.format("%Y-%m-%d %H:%M:%S")
-                );
-            } else {
-                println!(
-                    "  + {}  {}",
-                    migration.id,
-                    migration.applied_at.format("%Y-%m-%d %H:%M:%S")
-                );
+                notes.push("baseline");
+            }
+            if is_reversible {
+                notes.push("reversible");
+            }
+            if migration.applied_by_check {
+                notes.push("applied by check");
+            }
+            if is_modified {
+                notes.push("modified since applied");
             }
+
+            // Prefer the current header, falling back to the one recorded when applied
+            let description = available
+                .iter()
+                .find(|m| m.id == migration.id)
+                .and_then(|m| m.description())
+                .or(migration.description.as_deref());
+
+            let label = format!(
+                "{}  {}",
+                migration.id,
+                migration.applied_at.format("%Y-%m-%d %H:%M:%S")
+            );
+            print_migration('+', &label, description, &notes);
         }
         println!();
+
+        if !modified.is_empty() {
+            println!(
+                "Warning: {} applied migration(s) changed since they were applied (see `migrate verify`)",
+                modified.len()
+            );
+            println!();
+        }
     }
 
     // Show pending migrations
     if !pending.is_empty() {
         println!("Pending ({}):", pending.len());
         for migration in &pending {
-            println!("  - {}", migration.id);
+            let mut notes = Vec::new();
+            if out_of_order.iter().any(|m| m.id == migration.id) {
+                notes.push("out of order");
+            }
+            if !selection.tags.matches(migration) {
+                notes.push("filtered by tag");
+            } else if !selected.iter().any(|m| m.id == migration.id) {
+                notes.push("not selected");
+            }
+
+            print_migration('-', &migration.id, migration.description(), &notes);
+        }
+
+        if !out_of_order.is_empty() {
+            println!();
+            println!(
+                "Warning: {} pending migration(s) are older than the current version and would be applied out of order",
+                out_of_order.len()
+            );
+        }
+
+        if !selection.is_empty() {
+            println!();
+            println!(
+                "Selected: {} of {} pending migration(s) would be applied",
+                selected.len(),
+                pending.len()
+            );
         }
     }
 
     Ok(())
 }
 
+/// Print one migration line: "  <marker> <label>  <description>  (<notes>)"
+fn print_migration(marker: char, label: &str, description: Option<&str>, notes: &[&str]) {
+    let mut line = format!("  {} {}", marker, label);
+    if let Some(description) = description {
+        line.push_str("  ");
+        line.push_str(description);
+    }
+    if !notes.is_empty() {
+        line.push_str(&format!("  ({})", notes.join(", ")));
+    }
+    println!("{}", line);
+}
+
 /// Extract version from a migration ID (e.g., "1f72f-init" -> "1f72f")
 fn extract_version(id: &str) -> Option<String> {
     if id.len() >= 5 && id.chars().nth(5) == Some('-') {
@@ -0,0 +1,301 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::baseline::read_baseline;
+use crate::config::interpreter_for;
+use crate::executor::execute;
+use crate::loader::{discover_migrations, FIXTURE_SUFFIX};
+use crate::snapshot::{Scratch, Snapshot};
+use crate::state::{get_pending, read_history};
+use crate::tree::{compare_trees, copy_tree, render_changes, walk, IgnoreRules};
+use crate::{Direction, ExecutionContext, Migration, Phase};
+
+/// Options for testing migrations
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// Only test the fixture of this migration (ID or version)
+    pub id: Option<String>,
+    /// Re-record the expected output of fixtures instead of comparing against it
+    pub update: bool,
+    /// Run each pending migration twice and check that the second run is a no-op
+    pub idempotent: bool,
+    /// Print differences as a unified diff instead of a summary
+    pub diff: bool,
+    /// Timeout for migrations that don't set their own `Timeout:` header
+    pub timeout: Option<Duration>,
+    /// Extra environment variables passed to every migration
+    pub env: BTreeMap<String, String>,
+    /// Interpreter commands by migration file extension
+    pub interpreters: BTreeMap<String, String>,
+}
+
+/// Test migrations against their fixtures: `<id>.test/before/` is copied to a temporary
+/// directory, the migration runs there, and the result must match `<id>.test/after/`.
+/// With `update`, the result is recorded as the new `after/` instead.
+/// With `idempotent`, pending migrations are tested for idempotency instead.
+pub fn run(project_root: &Path, migrations_dir: &Path, options: &TestOptions) -> Result<()> {
+    let project_root = if project_root.is_absolute() {
+        project_root.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(project_root)
+    };
+
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    if !migrations_path.exists() {
+        println!(
+            "No migrations directory found at: {}",
+            migrations_path.display()
+        );
+        return Ok(());
+    }
+
+    if options.idempotent {
+        test_idempotent(&project_root, &migrations_path, options)
+    } else {
+        test_fixtures(&migrations_path, options)
+    }
+}
+
+/// Directory holding the fixture of a migration: `<id>.test` in the migrations directory.
+pub fn fixture_dir(migrations_dir: &Path, id: &str) -> PathBuf {
+    migrations_dir.join(format!("{}{}", id, FIXTURE_SUFFIX))
+}
+
+fn test_fixtures(migrations_path: &Path, options: &TestOptions) -> Result<()> {
+    let available = discover_migrations(migrations_path)?;
+
+    let migrations: Vec<&Migration> = match &options.id {
+        Some(id) => {
+            let Some(migration) = available.iter().find(|m| m.id == *id || m.version == *id) else {
+                bail!("Migration '{}' not found", id);
+            };
+            if !fixture_dir(migrations_path, &migration.id).is_dir() {
+                bail!(
+                    "Migration '{}' has no fixture. Create {} with before/ and after/ directories.",
+                    migration.id,
+                    fixture_dir(migrations_path, &migration.id).display()
+                );
+            }
+            vec![migration]
+        }
+        None => available
+            .iter()
+            .filter(|m| fixture_dir(migrations_path, &m.id).is_dir())
+            .collect(),
+    };
+
+    if migrations.is_empty() {
+        println!("No migration fixtures found.");
+        println!("Add <id>.test/before/ and <id>.test/after/ directories next to a migration to test it.");
+        return Ok(());
+    }
+
+    println!(
+        "{} {} migration(s) against their fixtures...",
+        if options.update {
+            "Recording"
+        } else {
+            "Testing"
+        },
+        migrations.len()
+    );
+    println!();
+
+    let rules = IgnoreRules::default();
+    let mut failed = Vec::new();
+    for migration in migrations {
+        println!("→ {}", migration.id);
+
+        let fixture = fixture_dir(migrations_path, &migration.id);
+        let before = fixture.join("before");
+        let after = fixture.join("after");
+
+        // A missing before/ stands for an empty project, since git doesn't keep empty directories
+        let empty = tempfile::tempdir().context("Failed to create scratch directory")?;
+        let source = if before.is_dir() {
+            before.as_path()
+        } else {
+            empty.path()
+        };
+        let scratch = Scratch::create(source, migrations_path, &rules)?;
+
+        let ctx = ExecutionContext {
+            project_root: scratch.project_root().to_path_buf(),
+            migrations_dir: scratch.migrations_dir().to_path_buf(),
+            migration_id: migration.id.clone(),
+            dry_run: false,
+            direction: Direction::Up,
+            phase: Phase::Run,
+            timeout: migration.timeout.or(options.timeout),
+            log_path: None,
+            env: options.env.clone(),
+            interpreter: interpreter_for(&options.interpreters, &migration.file_path),
+        };
+
+        let result = execute(migration, &ctx)?;
+        if !result.success {
+            println!("  ✗ failed");
+            print_error(result.error.as_deref());
+            failed.push(migration.id.clone());
+            continue;
+        }
+
+        if options.update {
+            if after.exists() {
+                fs::remove_dir_all(&after)
+                    .with_context(|| format!("Failed to remove {}", after.display()))?;
+            }
+            fs::create_dir_all(&after)?;
+            let tree = walk(scratch.project_root(), &rules)?;
+            copy_tree(&tree, scratch.project_root(), &after)?;
+            println!("  ✓ recorded {} file(s)", tree.files.len());
+            continue;
+        }
+
+        if !after.is_dir() {
+            println!("  ✗ no expected output (run with --update to record it)");
+            failed.push(migration.id.clone());
+            continue;
+        }
+
+        let changes = compare_trees(&after, scratch.project_root(), &rules)?;
+        if changes.is_empty() {
+            println!("  ✓ matches expected output");
+        } else {
+            println!("  ✗ {} file(s) differ from expected output:", changes.len());
+            print!(
+                "{}",
+                render_changes(&changes, &after, scratch.project_root(), options.diff)?
+            );
+            failed.push(migration.id.clone());
+        }
+    }
+
+    println!();
+    if !failed.is_empty() {
+        bail!(
+            "{} migration(s) failed their fixture test: {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+    if options.update {
+        println!("Fixtures updated.");
+    } else {
+        println!("All fixtures pass.");
+    }
+    Ok(())
+}
+
+/// Run each pending migration twice in a scratch copy of the project.
+/// A migration fails the test if its second run fails or changes files again.
+fn test_idempotent(
+    project_root: &Path,
+    migrations_path: &Path,
+    options: &TestOptions,
+) -> Result<()> {
+    let available = discover_migrations(migrations_path)?;
+    let applied = read_history(migrations_path)?;
+    let baseline = read_baseline(migrations_path)?;
+    let pending = get_pending(&available, &applied, baseline.as_ref());
+
+    if pending.is_empty() {
+        println!("No pending migrations.");
+        return Ok(());
+    }
+
+    println!(
+        "Testing {} pending migration(s) for idempotency in a scratch copy...",
+        pending.len()
+    );
+    println!();
+
+    let ignore_rules = IgnoreRules::load(project_root, migrations_path)?;
+    let scratch = Scratch::create(project_root, migrations_path, &ignore_rules)?;
+
+    let mut failed = Vec::new();
+    for migration in &pending {
+        match migration.description() {
+            Some(description) => println!("→ {}  {}", migration.id, description),
+            None => println!("→ {}", migration.id),
+        }
+
+        let ctx = ExecutionContext {
+            project_root: scratch.project_root().to_path_buf(),
+            migrations_dir: scratch.migrations_dir().to_path_buf(),
+            migration_id: migration.id.clone(),
+            dry_run: false,
+            direction: Direction::Up,
+            phase: Phase::Run,
+            timeout: migration.timeout.or(options.timeout),
+            log_path: None,
+            env: options.env.clone(),
+            interpreter: interpreter_for(&options.interpreters, &migration.file_path),
+        };
+
+        let first = Snapshot::capture(scratch.project_root(), &ignore_rules)?;
+        let result = execute(migration, &ctx)?;
+        if !result.success {
+            // Later migrations may depend on this one, so testing them would be meaningless
+            println!("  ✗ first run failed");
+            print_error(result.error.as_deref());
+            failed.push(migration.id.clone());
+            break;
+        }
+        let first_changes = first.changes()?;
+
+        let second = Snapshot::capture(scratch.project_root(), &ignore_rules)?;
+        let result = execute(migration, &ctx)?;
+        if !result.success {
+            println!("  ✗ second run failed");
+            print_error(result.error.as_deref());
+            failed.push(migration.id.clone());
+            continue;
+        }
+
+        let changes = second.changes()?;
+        if changes.is_empty() {
+            println!(
+                "  ✓ idempotent ({} file(s) changed by the first run)",
+                first_changes.len()
+            );
+        } else {
+            println!("  ✗ second run changed {} file(s):", changes.len());
+            print!(
+                "{}",
+                render_changes(
+                    &changes,
+                    second.storage_path(),
+                    scratch.project_root(),
+                    options.diff
+                )?
+            );
+            failed.push(migration.id.clone());
+        }
+    }
+
+    println!();
+    if !failed.is_empty() {
+        bail!(
+            "{} migration(s) failed the idempotency test: {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+    println!("All {} migration(s) are idempotent.", pending.len());
+    Ok(())
+}
+
+fn print_error(error: Option<&str>) {
+    if let Some(error) = error {
+        println!("    {}", error);
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::baseline::read_baseline;
+use crate::journal::Journal;
+use crate::lock::Lock;
+use crate::state::{find_applied, read_history, remove_history};
+use crate::tree::{hash_file, set_file_mode, FileChange};
+
+/// Undo applied migrations by restoring the files they changed from the change journal.
+/// Undoes the given migration, or the last `last` migrations (default: 1).
+/// `wait` is how long to wait for another run holding the migrations lock.
+pub fn run(
+    project_root: &Path,
+    migrations_dir: &Path,
+    id: Option<&str>,
+    last: Option<usize>,
+    force: bool,
+    dry_run: bool,
+    wait: Option<Duration>,
+) -> Result<()> {
+    let project_root = if project_root.is_absolute() {
+        project_root.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(project_root)
+    };
+
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    if !migrations_path.exists() {
+        println!(
+            "No migrations directory found at: {}",
+            migrations_path.display()
+        );
+        return Ok(());
+    }
+
+    // Resolve an explicit ID against history before touching anything on disk,
+    // so arbitrary input never reaches the journal
+    let resolved = match id {
+        Some(id) => match find_applied(&read_history(&migrations_path)?, id) {
+            Some(a) => Some(a.id.clone()),
+            None => bail!("Migration '{}' has not been applied", id),
+        },
+        None => None,
+    };
+
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(Lock::acquire(&migrations_path, wait)?)
+    };
+
+    let applied = read_history(&migrations_path)?;
+    let baseline = read_baseline(&migrations_path)?;
+    let journal = Journal::open(&migrations_path);
+
+    // Most recently applied first
+    let targets: Vec<&str> = match &resolved {
+        // Another run may have reverted it while we waited for the lock
+        Some(id) if !applied.iter().any(|a| &a.id == id) => {
+            bail!("Migration '{}' has not been applied", id)
+        }
+        Some(id) => vec![id.as_str()],
+        None => applied
+            .iter()
+            .rev()
+            .take(last.unwrap_or(1))
+            .map(|a| a.id.as_str())
+            .collect(),
+    };
+
+    if targets.is_empty() {
+        println!("No migrations to undo.");
+        return Ok(());
+    }
+
+    let mut plans: Vec<(&str, Vec<FileChange>)> = Vec::new();
+    for target in &targets {
+        if let Some(b) = &baseline {
+            if target.get(..5).is_some_and(|v| v <= b.version.as_str()) {
+                bail!(
+                    "Cannot undo migration '{}': it is covered by the baseline at '{}'",
+                    target,
+                    b.version
+                );
+            }
+        }
+
+        let Some(changes) = journal.read_manifest(target)? else {
+            bail!(
+                "Cannot undo migration '{}': no change journal recorded",
+                target
+            );
+        };
+        plans.push((target, changes));
+    }
+
+    // Walk the undo sequence against the expected file states before touching anything,
+    // so that a conflict in an older migration doesn't leave newer ones half undone
+    let conflicts = find_conflicts(&project_root, &plans)?;
+    if !conflicts.is_empty() && !force {
+        let mut message = String::from("Files changed since the migration ran:");
+        for (id, path) in &conflicts {
+            message.push_str(&format!("\n  {} ({})", path.display(), id));
+        }
+        message.push_str("\nUse --force to overwrite them.");
+        bail!(message);
+    }
+
+    println!(
+        "{} {} migration(s)...",
+        if dry_run { "Would undo" } else { "Undoing" },
+        plans.len()
+    );
+    println!();
+
+    for (id, changes) in &plans {
+        println!("← {}", id);
+        for change in changes {
+            println!("  {}  {}", change.kind().symbol(), change.path.display());
+        }
+
+        if dry_run {
+            println!("  (dry run - skipped)");
+            continue;
+        }
+
+        for change in changes {
+            restore_file(&journal, &project_root, change)?;
+        }
+
+        remove_history(&migrations_path, &[*id])?;
+        journal.remove_manifest(id)?;
+        println!("  ✓ undone");
+    }
+
+    println!();
+    println!("All migrations undone successfully.");
+
+    Ok(())
+}
+
+/// Find files whose current content no longer matches what the migration left behind.
+fn find_conflicts(
+    project_root: &Path,
+    plans: &[(&str, Vec<FileChange>)],
+) -> Result<Vec<(String, PathBuf)>> {
+    let mut expected: HashMap<&Path, Option<String>> = HashMap::new();
+    let mut conflicts = Vec::new();
+
+    for (id, changes) in plans {
+        for change in changes {
+            let current = match expected.get(change.path.as_path()) {
+                Some(state) => state.clone(),
+                None => current_hash(&project_root.join(&change.path))?,
+            };
+            if current != change.new_hash {
+                conflicts.push((id.to_string(), change.path.clone()));
+            }
+            expected.insert(&change.path, change.old_hash.clone());
+        }
+    }
+
+    Ok(conflicts)
+}
+
+fn current_hash(path: &Path) -> Result<Option<String>> {
+    if path.is_file() {
+        hash_file(path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Put a file back to its pre-migration content and permissions, or remove it if the
+/// migration created it, along with any directories that removal leaves empty.
+fn restore_file(journal: &Journal, project_root: &Path, change: &FileChange) -> Result<()> {
+    let path = project_root.join(&change.path);
+
+    match &change.old_hash {
+        Some(hash) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, journal.read_object(hash)?)
+                .with_context(|| format!("Failed to restore file: {}", path.display()))?;
+            if let Some(mode) = change.old_mode {
+                set_file_mode(&path, mode)?;
+            }
+        }
+        None => {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove file: {}", path.display()))?;
+            }
+            remove_empty_parents(project_root, &path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the directories above `path` that are empty, stopping at the project root.
+fn remove_empty_parents(project_root: &Path, path: &Path) -> Result<()> {
+    for dir in path.ancestors().skip(1) {
+        if dir == project_root || !dir.starts_with(project_root) {
+            break;
+        }
+        let is_empty = match fs::read_dir(dir) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(_) => break,
+        };
+        if !is_empty {
+            break;
+        }
+        fs::remove_dir(dir)
+            .with_context(|| format!("Failed to remove directory: {}", dir.display()))?;
+    }
+    Ok(())
+}
@@ -1,21 +1,76 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use chrono::Utc;
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use crate::baseline::{delete_baselined_migrations, read_baseline, write_baseline, Baseline};
+use crate::config::interpreter_for;
 use crate::executor::execute;
-use crate::loader::discover_migrations;
-use crate::state::{append_history, get_pending, read_history};
-use crate::ExecutionContext;
-
-/// Apply all pending migrations
-pub fn run(
-    project_root: &Path,
-    migrations_dir: &Path,
-    dry_run: bool,
-    create_baseline: bool,
-    keep: bool,
-) -> Result<()> {
+use crate::host;
+use crate::journal::Journal;
+use crate::loader::{discover_migrations, read_interpreter};
+use crate::lock::Lock;
+use crate::logs::{new_log_path, prune_logs};
+use crate::selection::Selection;
+use crate::snapshot::{Scratch, Snapshot};
+use crate::state::{
+    append_history, get_current_version, get_out_of_order, get_pending, read_history,
+    OutOfOrderPolicy,
+};
+use crate::tree::{compare_trees, render_changes, IgnoreRules};
+use crate::{AppliedMigration, Direction, ExecutionContext, Phase};
+
+/// Options for applying migrations
+#[derive(Debug, Clone, Default)]
+pub struct UpOptions {
+    /// Run migrations in a scratch copy and report the changes they would make
+    pub dry_run: bool,
+    /// Print dry run changes as a unified diff instead of a summary
+    pub diff: bool,
+    /// Create a baseline at the final version after applying
+    pub create_baseline: bool,
+    /// Keep migration files when creating a baseline
+    pub keep: bool,
+    /// Restore the project tree if a migration fails
+    pub atomic: bool,
+    /// Timeout for migrations that don't set their own `Timeout:` header
+    pub timeout: Option<Duration>,
+    /// Number of run logs kept per migration; 0 disables output capture
+    pub log_retention: usize,
+    /// How long to wait for another run holding the migrations lock
+    pub wait: Option<Duration>,
+    /// Which pending migrations to apply
+    pub selection: Selection,
+    /// How to handle pending migrations older than the current version
+    pub out_of_order: OutOfOrderPolicy,
+    /// Extra environment variables passed to every migration
+    pub env: BTreeMap<String, String>,
+    /// Interpreter commands by migration file extension
+    pub interpreters: BTreeMap<String, String>,
+}
+
+/// Apply all pending migrations.
+/// With `atomic`, the project tree is restored if a migration fails.
+/// With `dry_run`, migrations run in a scratch copy and the resulting changes are
+/// printed as a summary, or as a unified diff with `diff`.
+/// Only the pending migrations picked by the selection are applied.
+pub fn run(project_root: &Path, migrations_dir: &Path, options: &UpOptions) -> Result<()> {
+    let UpOptions {
+        dry_run,
+        diff,
+        create_baseline,
+        keep,
+        atomic,
+        timeout,
+        log_retention,
+        wait,
+        ref selection,
+        out_of_order,
+        ref env,
+        ref interpreters,
+    } = *options;
+
     let project_root = if project_root.is_absolute() {
         project_root.to_path_buf()
     } else {
@@ -36,63 +91,240 @@ pub fn run(
         return Ok(());
     }
 
+    // Held until the run finishes, so concurrent runs can't apply the same migration twice
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(Lock::acquire(&migrations_path, wait)?)
+    };
+
     let available = discover_migrations(&migrations_path)?;
     let applied = read_history(&migrations_path)?;
     let baseline = read_baseline(&migrations_path)?;
-    let pending = get_pending(&available, &applied, baseline.as_ref());
+    let all_pending = get_pending(&available, &applied, baseline.as_ref());
+    let pending = selection.apply(&all_pending)?;
 
-    if pending.is_empty() {
+    if all_pending.is_empty() {
         println!("No pending migrations.");
         return Ok(());
     }
+    if pending.is_empty() {
+        println!("No pending migrations match the selection.");
+        return Ok(());
+    }
+
+    // Baselining deletes every migration at or below the last applied version,
+    // so pending migrations the selection leaves out must not fall in that range
+    if create_baseline {
+        let final_version = pending
+            .last()
+            .map(|m| m.version.as_str())
+            .unwrap_or_default();
+        let unselected: Vec<&str> = all_pending
+            .iter()
+            .filter(|m| m.version.as_str() <= final_version)
+            .filter(|m| !pending.iter().any(|p| p.id == m.id))
+            .map(|m| m.id.as_str())
+            .collect();
+        if !unselected.is_empty() {
+            bail!(
+                "Cannot baseline at '{}': pending migration(s) not selected would be deleted: {}",
+                final_version,
+                unselected.join(", ")
+            );
+        }
+    }
+
+    let current_version = get_current_version(&available, &applied);
+    let older = get_out_of_order(&available, &applied, &pending);
+    if !older.is_empty() {
+        let ids: Vec<&str> = older.iter().map(|m| m.id.as_str()).collect();
+        match out_of_order {
+            OutOfOrderPolicy::Error => bail!(
+                "{} pending migration(s) are older than the current version '{}': {}\nRename them to a newer version, or use --out-of-order warn or allow to apply them anyway.",
+                older.len(),
+                current_version.unwrap_or_default(),
+                ids.join(", ")
+            ),
+            OutOfOrderPolicy::Warn => {
+                println!(
+                    "Warning: {} pending migration(s) are older than the current version '{}' and will be applied out of order: {}",
+                    older.len(),
+                    current_version.as_deref().unwrap_or_default(),
+                    ids.join(", ")
+                );
+                println!();
+            }
+            OutOfOrderPolicy::Allow => {}
+        }
+    }
+
+    if dry_run {
+        println!("Dry run - running migrations in a scratch copy, no changes will be made");
+        println!();
+    }
 
     println!(
-        "{} {} migration(s)...",
+        "{} {} migration(s){}...",
         if dry_run { "Would apply" } else { "Applying" },
-        pending.len()
+        pending.len(),
+        if dry_run { " (dry run)" } else { "" }
     );
+    if pending.len() < all_pending.len() {
+        println!(
+            "Leaving {} pending migration(s) not selected",
+            all_pending.len() - pending.len()
+        );
+    }
     println!();
 
+    let ignore_rules = IgnoreRules::load(&project_root, &migrations_path)?;
+
+    // A dry run executes against a throwaway copy of the project
+    let scratch = if dry_run {
+        Some(Scratch::create(
+            &project_root,
+            &migrations_path,
+            &ignore_rules,
+        )?)
+    } else {
+        None
+    };
+    let (exec_root, exec_migrations) = match &scratch {
+        Some(s) => (
+            s.project_root().to_path_buf(),
+            s.migrations_dir().to_path_buf(),
+        ),
+        None => (project_root.clone(), migrations_path.clone()),
+    };
+
+    let journal = Journal::open(&migrations_path);
     let mut last_applied_version: Option<String> = None;
 
     for migration in &pending {
-        println!("→ {}", migration.id);
-
-        if dry_run {
-            println!("  (dry run - skipped)");
-            last_applied_version = Some(migration.version.clone());
-            continue;
+        match migration.description() {
+            Some(description) => println!("→ {}  {}", migration.id, description),
+            None => println!("→ {}", migration.id),
         }
 
+        // Dry runs leave the migrations directory untouched, logs included
+        let log_path =
+            (!dry_run && log_retention > 0).then(|| new_log_path(&migrations_path, &migration.id));
+
         let ctx = ExecutionContext {
-            project_root: project_root.clone(),
-            migrations_dir: migrations_path.clone(),
+            project_root: exec_root.clone(),
+            migrations_dir: exec_migrations.clone(),
             migration_id: migration.id.clone(),
             dry_run,
+            direction: Direction::Up,
+            phase: Phase::Run,
+            timeout: migration.timeout.or(timeout),
+            log_path: log_path.clone(),
+            env: env.clone(),
+            interpreter: interpreter_for(interpreters, &migration.file_path),
         };
 
-        let result = execute(migration, &ctx)?;
+        // The snapshot feeds both the change journal and --atomic restores
+        let snapshot = Snapshot::capture(&exec_root, &ignore_rules)?;
+
+        let started = Instant::now();
+
+        // A satisfied or failed check ends the migration without the real run
+        let check = if migration.check {
+            let check_ctx = ExecutionContext {
+                phase: Phase::Check,
+                ..ctx.clone()
+            };
+            Some(execute(migration, &check_ctx)?).filter(|r| r.satisfied || !r.success)
+        } else {
+            None
+        };
+        let result = match check {
+            Some(result) => result,
+            None => execute(migration, &ctx)?,
+        };
+        if log_path.is_some() {
+            prune_logs(&migrations_path, &migration.id, log_retention)?;
+        }
 
         if result.success {
-            let applied_at = Utc::now();
-            append_history(&migrations_path, &migration.id, applied_at)?;
+            let changes = snapshot.changes()?;
+
+            if !dry_run {
+                journal.record(
+                    &migration.id,
+                    &changes,
+                    snapshot.storage_path(),
+                    &project_root,
+                )?;
+
+                let record = AppliedMigration {
+                    id: migration.id.clone(),
+                    applied_at: Utc::now(),
+                    checksum: Some(migration.checksum()?),
+                    duration_ms: Some(started.elapsed().as_millis() as u64),
+                    exit_code: Some(result.exit_code),
+                    migrate_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                    hostname: host::hostname(),
+                    user: host::username(),
+                    interpreter: ctx
+                        .interpreter
+                        .clone()
+                        .or_else(|| read_interpreter(&migration.file_path)),
+                    description: migration.description().map(|d| d.to_string()),
+                    tag_filter: (!selection.tags.is_empty()).then(|| selection.tags.clone()),
+                    applied_by_check: result.satisfied,
+                };
+                append_history(&migrations_path, &record)?;
+            }
+
             last_applied_version = Some(migration.version.clone());
-            println!("  ✓ completed");
+            if result.satisfied {
+                println!("  ✓ already satisfied (check passed, not run)");
+            } else {
+                println!("  ✓ completed ({} file(s) changed)", changes.len());
+            }
         } else {
-            println!("  ✗ failed (exit code {})", result.exit_code);
-            if let Some(error) = result.error {
+            if result.timed_out {
+                println!("  ✗ timed out");
+            } else {
+                println!("  ✗ failed (exit code {})", result.exit_code);
+            }
+            if let Some(error) = &result.error {
                 println!("    {}", error);
             }
-            return Err(anyhow::anyhow!(
-                "Migration {} failed with exit code {}",
-                migration.id,
-                result.exit_code
-            ));
+            if let Some(path) = &log_path {
+                println!("    Log: {}", path.display());
+            }
+            if atomic {
+                snapshot.restore()?;
+                println!("  ↺ restored project files");
+            }
+            bail!(result
+                .error
+                .unwrap_or_else(|| format!("Migration {} failed", migration.id)));
         }
     }
 
     println!();
-    println!("All migrations applied successfully.");
+
+    if dry_run {
+        let changes = compare_trees(&project_root, &exec_root, &ignore_rules)?;
+        if changes.is_empty() {
+            println!("Dry run complete - no files would change.");
+        } else {
+            println!("Dry run complete - {} file(s) would change:", changes.len());
+            if diff {
+                println!();
+            }
+            print!(
+                "{}",
+                render_changes(&changes, &project_root, &exec_root, diff)?
+            );
+        }
+    } else {
+        println!("All migrations applied successfully.");
+    }
 
     // Handle --baseline flag
     if create_baseline {
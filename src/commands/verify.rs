@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use crate::loader::discover_migrations;
+use crate::state::{get_modified, read_history};
+
+/// Verify that applied migration files haven't changed since they were applied.
+/// Fails if any checksum doesn't match, so it can gate CI.
+pub fn run(project_root: &Path, migrations_dir: &Path) -> Result<()> {
+    let migrations_path = if migrations_dir.is_absolute() {
+        migrations_dir.to_path_buf()
+    } else {
+        project_root.join(migrations_dir)
+    };
+
+    if !migrations_path.exists() {
+        println!(
+            "No migrations directory found at: {}",
+            migrations_path.display()
+        );
+        return Ok(());
+    }
+
+    let available = discover_migrations(&migrations_path)?;
+    let applied = read_history(&migrations_path)?;
+    let modified = get_modified(&available, &applied)?;
+
+    let mut verified = 0;
+    let mut unchecked = 0;
+    for entry in &applied {
+        let exists = available.iter().any(|m| m.id == entry.id);
+        if modified.iter().any(|m| m.id == entry.id) {
+            println!("  ✗ {}  (modified since applied)", entry.id);
+        } else if entry.checksum.is_none() {
+            println!("  ? {}  (no checksum recorded)", entry.id);
+            unchecked += 1;
+        } else if !exists {
+            println!("  ? {}  (file not found)", entry.id);
+            unchecked += 1;
+        } else {
+            println!("  ✓ {}", entry.id);
+            verified += 1;
+        }
+    }
+
+    println!();
+
+    if !modified.is_empty() {
+        bail!(
+            "{} applied migration(s) changed since they were applied",
+            modified.len()
+        );
+    }
+
+    println!(
+        "{} migration(s) verified, {} without a checksum to compare.",
+        verified, unchecked
+    );
+
+    Ok(())
+}
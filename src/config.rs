@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::duration::parse_duration;
+
+/// Name of the configuration file at the project root
+pub const CONFIG_FILE: &str = "migrate.toml";
+
+/// Project settings from `migrate.toml`. Command-line flags take precedence.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Migrations directory, relative to the project root
+    pub migrations: Option<PathBuf>,
+    /// Template used by `migrate create` when `--template` isn't given
+    pub template: Option<String>,
+    /// Timeout for migrations without a `Timeout:` header (e.g., "10m")
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub timeout: Option<Duration>,
+    /// Extra environment variables passed to every migration
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Commands that run migrations by file extension (e.g., `ts = "npx tsx"`),
+    /// instead of executing the file itself
+    #[serde(default)]
+    pub interpreters: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Load `migrate.toml` from the project root, or the defaults if there is none.
+    pub fn load(project_root: &Path) -> Result<Self> {
+        let path = project_root.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        parse_config(&content).with_context(|| format!("Invalid config file: {}", path.display()))
+    }
+}
+
+fn parse_config(content: &str) -> Result<Config> {
+    Ok(toml::from_str(content)?)
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Interpreter command configured for a migration file's extension, if any.
+pub fn interpreter_for(interpreters: &BTreeMap<String, String>, path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    interpreters
+        .get(extension)
+        .filter(|command| !command.trim().is_empty())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse_config(
+            r#"
+migrations = "db/migrations"
+template = "ts"
+timeout = "10m"
+
+[env]
+NODE_ENV = "test"
+
+[interpreters]
+ts = "npx tsx"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.migrations, Some(PathBuf::from("db/migrations")));
+        assert_eq!(config.template.as_deref(), Some("ts"));
+        assert_eq!(config.timeout, Some(Duration::from_secs(600)));
+        assert_eq!(config.env["NODE_ENV"], "test");
+        assert_eq!(
+            interpreter_for(&config.interpreters, Path::new("1f700-a.down.ts")).as_deref(),
+            Some("npx tsx")
+        );
+        assert_eq!(
+            interpreter_for(&config.interpreters, Path::new("1f700-a.sh")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_config_errors_name_the_key() {
+        let err = format!("{:#}", parse_config("migration = \"db\"\n").unwrap_err());
+        assert!(err.contains("unknown field `migration`"), "{}", err);
+
+        let err = format!("{:#}", parse_config("timeout = \"soon\"\n").unwrap_err());
+        assert!(err.contains("line 1") && err.contains("timeout"), "{}", err);
+    }
+}
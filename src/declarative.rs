@@ -0,0 +1,470 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::json::{self, JsonStyle};
+use crate::logs::RunLog;
+use crate::tree::{copy_tree, resolve_within, walk, IgnoreRules};
+use crate::ExecutionContext;
+
+/// File name suffix of declarative migrations (e.g., `1fb2g-prettier.migrate.toml`)
+pub const EXTENSION: &str = ".migrate.toml";
+
+/// Whether a migration script is a declarative list of operations rather than a program.
+pub fn is_declarative(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|f| f.to_str())
+        .is_some_and(|name| name.ends_with(EXTENSION))
+}
+
+/// The content of a declarative migration
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Plan {
+    #[serde(default)]
+    ops: Vec<Operation>,
+}
+
+/// A file operation. Paths are relative to the project root, except `asset`, which is
+/// relative to the migration's asset directory (`<id>/` in the migrations directory).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum Operation {
+    /// Copy a file or directory from the project or from the migration's assets
+    Copy {
+        from: Option<String>,
+        asset: Option<String>,
+        to: String,
+    },
+    /// Move or rename a file or directory
+    Move { from: String, to: String },
+    /// Delete a file or directory, if it exists
+    Delete { path: String },
+    /// Create a directory and its parents
+    Mkdir { path: String },
+    /// Write a file, replacing its content
+    Write { path: String, content: String },
+    /// Set a value in a JSON file, creating the file and missing parent objects
+    JsonSet {
+        path: String,
+        pointer: String,
+        value: toml::Value,
+    },
+    /// Delete a value from a JSON file, if it is there
+    JsonDelete { path: String, pointer: String },
+    /// Replace every match of a regular expression in a text file
+    Replace {
+        path: String,
+        pattern: String,
+        replacement: String,
+    },
+}
+
+/// An operation whose paths have been resolved and checked
+#[derive(Debug)]
+enum Step {
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Delete(PathBuf),
+    Mkdir(PathBuf),
+    Write {
+        path: PathBuf,
+        content: String,
+    },
+    JsonSet {
+        path: PathBuf,
+        pointer: String,
+        value: Value,
+    },
+    JsonDelete {
+        path: PathBuf,
+        pointer: String,
+    },
+    Replace {
+        path: PathBuf,
+        pattern: Regex,
+        replacement: String,
+    },
+}
+
+/// Where the paths of a migration's operations may point
+struct Roots<'a> {
+    project: &'a Path,
+    migrations: &'a Path,
+    assets: PathBuf,
+}
+
+impl Roots<'_> {
+    /// A path the migration reads from: anywhere in the project.
+    fn source(&self, path: &str) -> Result<PathBuf> {
+        resolve_within(self.project, path)
+    }
+
+    /// A path the migration changes: anywhere in the project but the migrations directory.
+    fn target(&self, path: &str) -> Result<PathBuf> {
+        let resolved = resolve_within(self.project, path)?;
+        if resolved.starts_with(self.migrations) {
+            bail!("Path '{}' is inside the migrations directory", path);
+        }
+        Ok(resolved)
+    }
+
+    /// A file from the migration's asset directory.
+    fn asset(&self, path: &str) -> Result<PathBuf> {
+        if !self.assets.is_dir() {
+            bail!(
+                "Asset '{}' not found: there is no asset directory {}",
+                path,
+                self.assets.display()
+            );
+        }
+        resolve_within(&self.assets, path)
+    }
+}
+
+/// Apply a declarative migration. Every operation is parsed and its paths checked before
+/// any of them is applied; they are then applied in order, and each one is printed and logged.
+pub fn apply(script: &Path, ctx: &ExecutionContext, log: Option<&RunLog>) -> Result<()> {
+    let content = fs::read_to_string(script)
+        .with_context(|| format!("Failed to read migration: {}", script.display()))?;
+    let plan: Plan =
+        toml::from_str(&content).with_context(|| format!("Invalid {}", script.display()))?;
+
+    let roots = Roots {
+        project: &ctx.project_root,
+        migrations: &ctx.migrations_dir,
+        assets: ctx.migrations_dir.join(&ctx.migration_id),
+    };
+    let steps = plan
+        .ops
+        .iter()
+        .enumerate()
+        .map(|(i, op)| prepare(op, &roots).with_context(|| format!("Invalid operation {}", i + 1)))
+        .collect::<Result<Vec<_>>>()?;
+
+    for (op, step) in plan.ops.iter().zip(&steps) {
+        let description = describe(op, &ctx.migration_id);
+        println!("  {}", description);
+        if let Some(log) = log {
+            log.write_line("op", description.as_bytes())?;
+        }
+        apply_step(step).with_context(|| format!("Failed to {}", description))?;
+    }
+    Ok(())
+}
+
+fn prepare(op: &Operation, roots: &Roots) -> Result<Step> {
+    Ok(match op {
+        Operation::Copy { from, asset, to } => {
+            let from = match (from, asset) {
+                (Some(from), None) => roots.source(from)?,
+                (None, Some(asset)) => roots.asset(asset)?,
+                _ => bail!("copy needs exactly one of 'from' and 'asset'"),
+            };
+            Step::Copy {
+                from,
+                to: roots.target(to)?,
+            }
+        }
+        Operation::Move { from, to } => Step::Move {
+            from: roots.target(from)?,
+            to: roots.target(to)?,
+        },
+        Operation::Delete { path } => Step::Delete(roots.target(path)?),
+        Operation::Mkdir { path } => Step::Mkdir(roots.target(path)?),
+        Operation::Write { path, content } => Step::Write {
+            path: roots.target(path)?,
+            content: content.clone(),
+        },
+        Operation::JsonSet {
+            path,
+            pointer,
+            value,
+        } => {
+            json::parse_pointer(pointer)?;
+            Step::JsonSet {
+                path: roots.target(path)?,
+                pointer: pointer.clone(),
+                value: serde_json::to_value(value)?,
+            }
+        }
+        Operation::JsonDelete { path, pointer } => {
+            if json::parse_pointer(pointer)?.is_empty() {
+                bail!("Cannot delete the whole document of {}", path);
+            }
+            Step::JsonDelete {
+                path: roots.target(path)?,
+                pointer: pointer.clone(),
+            }
+        }
+        Operation::Replace {
+            path,
+            pattern,
+            replacement,
+        } => Step::Replace {
+            path: roots.target(path)?,
+            pattern: Regex::new(pattern)
+                .with_context(|| format!("Invalid pattern '{}'", pattern))?,
+            replacement: replacement.clone(),
+        },
+    })
+}
+
+fn describe(op: &Operation, id: &str) -> String {
+    match op {
+        Operation::Copy {
+            from: Some(from),
+            to,
+            ..
+        } => format!("copy {} -> {}", from, to),
+        Operation::Copy {
+            asset: Some(asset),
+            to,
+            ..
+        } => format!("copy {}/{} -> {}", id, asset, to),
+        Operation::Copy { to, .. } => format!("copy -> {}", to),
+        Operation::Move { from, to } => format!("move {} -> {}", from, to),
+        Operation::Delete { path } => format!("delete {}", path),
+        Operation::Mkdir { path } => format!("mkdir {}", path),
+        Operation::Write { path, .. } => format!("write {}", path),
+        Operation::JsonSet { path, pointer, .. } => format!("set {} in {}", pointer, path),
+        Operation::JsonDelete { path, pointer } => format!("delete {} in {}", pointer, path),
+        Operation::Replace { path, pattern, .. } => format!("replace /{}/ in {}", pattern, path),
+    }
+}
+
+fn apply_step(step: &Step) -> Result<()> {
+    match step {
+        Step::Copy { from, to } => {
+            create_parent(to)?;
+            if from.is_dir() {
+                let tree = walk(from, &IgnoreRules::default())?;
+                fs::create_dir_all(to)?;
+                copy_tree(&tree, from, to)?;
+            } else {
+                fs::copy(from, to)?;
+            }
+        }
+        Step::Move { from, to } => {
+            create_parent(to)?;
+            fs::rename(from, to)?;
+        }
+        Step::Delete(path) => match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+            Ok(_) => fs::remove_file(path)?,
+            Err(_) => {}
+        },
+        Step::Mkdir(path) => fs::create_dir_all(path)?,
+        Step::Write { path, content } => {
+            create_parent(path)?;
+            fs::write(path, content)?;
+        }
+        Step::JsonSet {
+            path,
+            pointer,
+            value,
+        } => {
+            let (mut document, style) = if path.exists() {
+                json::read_file(path)?
+            } else {
+                (Value::Object(Map::new()), JsonStyle::default())
+            };
+            json::set(&mut document, pointer, value.clone(), true)?;
+            create_parent(path)?;
+            json::write_file(path, &document, &style)?;
+        }
+        Step::JsonDelete { path, pointer } => {
+            if path.exists() {
+                let (mut document, style) = json::read_file(path)?;
+                if json::remove(&mut document, pointer)?.is_some() {
+                    json::write_file(path, &document, &style)?;
+                }
+            }
+        }
+        Step::Replace {
+            path,
+            pattern,
+            replacement,
+        } => {
+            let content = fs::read_to_string(path)?;
+            let replaced = pattern.replace_all(&content, replacement.as_str());
+            if replaced != content {
+                fs::write(path, replaced.as_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::execute;
+    use crate::{Direction, ExecutionResult, Migration, Phase};
+    use std::collections::BTreeMap;
+
+    fn run(project: &Path, content: &str) -> ExecutionResult {
+        let migrations_dir = project.join("migrations");
+        fs::create_dir_all(&migrations_dir).unwrap();
+        let script = migrations_dir.join("1fb2g-test.migrate.toml");
+        fs::write(&script, content).unwrap();
+
+        let migration = Migration {
+            id: "1fb2g-test".to_string(),
+            version: "1fb2g".to_string(),
+            file_path: script.clone(),
+            ..Default::default()
+        };
+        let ctx = ExecutionContext {
+            project_root: project.to_path_buf(),
+            migrations_dir,
+            migration_id: migration.id.clone(),
+            dry_run: false,
+            direction: Direction::Up,
+            phase: Phase::Run,
+            timeout: None,
+            log_path: None,
+            env: BTreeMap::new(),
+            interpreter: None,
+        };
+        execute(&migration, &ctx).unwrap()
+    }
+
+    #[test]
+    fn test_is_declarative() {
+        assert!(is_declarative(Path::new("m/1fb2g-foo.migrate.toml")));
+        assert!(is_declarative(Path::new("m/1fb2g-foo.down.migrate.toml")));
+        assert!(!is_declarative(Path::new("m/1fb2g-foo.toml")));
+    }
+
+    #[test]
+    fn test_apply_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+        fs::create_dir_all(project.join("migrations/1fb2g-test")).unwrap();
+        fs::write(project.join("migrations/1fb2g-test/.prettierrc"), "{}\n").unwrap();
+        fs::write(
+            project.join("package.json"),
+            "{\n    \"name\": \"app\"\n}\n",
+        )
+        .unwrap();
+        fs::write(project.join("README.md"), "Built with webpack 4\n").unwrap();
+        fs::create_dir_all(project.join("old")).unwrap();
+        fs::write(project.join("old/file.txt"), "old").unwrap();
+        fs::write(project.join("notes.txt"), "notes").unwrap();
+
+        let result = run(
+            project,
+            r#"
+[[ops]]
+type = "copy"
+asset = ".prettierrc"
+to = ".prettierrc"
+
+[[ops]]
+type = "json_set"
+path = "package.json"
+pointer = "/scripts/format"
+value = "prettier --write ."
+
+[[ops]]
+type = "replace"
+path = "README.md"
+pattern = 'webpack (\d+)'
+replacement = "vite (was webpack $1)"
+
+[[ops]]
+type = "move"
+from = "notes.txt"
+to = "docs/notes.txt"
+
+[[ops]]
+type = "delete"
+path = "old"
+
+[[ops]]
+type = "delete"
+path = "never-existed"
+
+[[ops]]
+type = "write"
+path = "config/app.env"
+content = "MODE=prod\n"
+"#,
+        );
+        assert!(result.success, "{:?}", result.error);
+
+        assert_eq!(
+            fs::read_to_string(project.join(".prettierrc")).unwrap(),
+            "{}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(project.join("package.json")).unwrap(),
+            "{\n    \"name\": \"app\",\n    \"scripts\": {\n        \"format\": \"prettier --write .\"\n    }\n}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(project.join("README.md")).unwrap(),
+            "Built with vite (was webpack 4)\n"
+        );
+        assert!(project.join("docs/notes.txt").exists());
+        assert!(!project.join("notes.txt").exists());
+        assert!(!project.join("old").exists());
+        assert_eq!(
+            fs::read_to_string(project.join("config/app.env")).unwrap(),
+            "MODE=prod\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_operations_change_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+
+        for (content, expected) in [
+            (
+                "[[ops]]\ntype = \"delete\"\npath = \"../outside\"\n",
+                "leads outside",
+            ),
+            (
+                "[[ops]]\ntype = \"delete\"\npath = \"migrations/x\"\n",
+                "inside the migrations directory",
+            ),
+            (
+                "[[ops]]\ntype = \"replace\"\npath = \"a\"\npattern = \"(\"\nreplacement = \"\"\n",
+                "Invalid pattern",
+            ),
+            ("[[ops]]\ntype = \"copy\"\nto = \"a\"\n", "exactly one of"),
+            (
+                "[[ops]]\ntype = \"chmod\"\npath = \"a\"\n",
+                "unknown variant",
+            ),
+        ] {
+            let with_write = format!(
+                "[[ops]]\ntype = \"write\"\npath = \"written\"\ncontent = \"\"\n\n{}",
+                content
+            );
+            let result = run(project, &with_write);
+            assert!(!result.success);
+            let error = result.error.unwrap();
+            assert!(error.contains(expected), "{}", error);
+            // Operations are checked before any is applied
+            assert!(!project.join("written").exists());
+        }
+    }
+}
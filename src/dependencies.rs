@@ -0,0 +1,202 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeSet;
+
+use crate::Migration;
+
+/// Dependencies declared in a migration's header
+#[derive(Debug, Clone, Default)]
+pub struct Declared {
+    /// Migrations that must run first, from `Requires:` headers (IDs or versions)
+    pub requires: Vec<String>,
+    /// Migrations that must run after this one, from `Before:` headers (IDs or versions)
+    pub before: Vec<String>,
+}
+
+/// Resolve declared dependencies and order migrations so that each one comes after
+/// everything it requires, falling back to version order.
+///
+/// `migrations` must be sorted by version, with `declared` in the same order.
+/// References to migrations covered by the baseline are accepted and dropped, since
+/// those migrations have already been applied everywhere.
+pub fn order_by_dependencies(
+    migrations: Vec<Migration>,
+    declared: &[Declared],
+    baseline_version: Option<&str>,
+) -> Result<Vec<Migration>> {
+    let count = migrations.len();
+    let mut requires: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); count];
+
+    let resolve = |from: &Migration, reference: &str, header: &str| -> Result<Option<usize>> {
+        if let Some(index) = migrations
+            .iter()
+            .position(|m| m.id == reference || m.version == reference)
+        {
+            return Ok(Some(index));
+        }
+        let baselined = reference
+            .get(..5)
+            .zip(baseline_version)
+            .is_some_and(|(version, baseline)| version <= baseline);
+        if baselined {
+            return Ok(None);
+        }
+        bail!(
+            "Migration '{}' has '{}: {}', but no such migration exists",
+            from.id,
+            header,
+            reference
+        );
+    };
+
+    for (index, (migration, declared)) in migrations.iter().zip(declared).enumerate() {
+        for reference in &declared.requires {
+            if let Some(required) = resolve(migration, reference, "Requires")? {
+                requires[index].insert(required);
+            }
+        }
+        for reference in &declared.before {
+            if let Some(dependent) = resolve(migration, reference, "Before")? {
+                requires[dependent].insert(index);
+            }
+        }
+    }
+
+    // Kahn's algorithm, always taking the lowest version among the ready migrations
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); count];
+    let mut remaining: Vec<usize> = vec![0; count];
+    for (index, required) in requires.iter().enumerate() {
+        remaining[index] = required.len();
+        for &r in required {
+            dependents[r].push(index);
+        }
+    }
+
+    let mut ready: BTreeSet<usize> = (0..count).filter(|&i| remaining[i] == 0).collect();
+    let mut order = Vec::with_capacity(count);
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+        for &dependent in &dependents[index] {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
+
+    if order.len() < count {
+        let cycle = find_cycle(&requires, &remaining);
+        let ids: Vec<&str> = cycle.iter().map(|&i| migrations[i].id.as_str()).collect();
+        bail!("Dependency cycle between migrations: {}", ids.join(" -> "));
+    }
+
+    let ids: Vec<String> = migrations.iter().map(|m| m.id.clone()).collect();
+    let mut slots: Vec<Option<Migration>> = migrations.into_iter().map(Some).collect();
+
+    Ok(order
+        .into_iter()
+        .filter_map(|index| {
+            let mut migration = slots[index].take()?;
+            migration.requires = requires[index].iter().map(|&r| ids[r].clone()).collect();
+            Some(migration)
+        })
+        .collect())
+}
+
+/// Walk requirements among the migrations left unordered until one repeats.
+/// Every such migration still has an unordered requirement, so this always finds a cycle.
+fn find_cycle(requires: &[BTreeSet<usize>], remaining: &[usize]) -> Vec<usize> {
+    let Some(start) = (0..remaining.len()).find(|&i| remaining[i] > 0) else {
+        return Vec::new();
+    };
+
+    let mut path = vec![start];
+    loop {
+        let current = *path.last().unwrap_or(&start);
+        let Some(&next) = requires[current].iter().find(|&&r| remaining[r] > 0) else {
+            return path;
+        };
+        if let Some(position) = path.iter().position(|&i| i == next) {
+            // Report the cycle in execution order: each migration requires the one before it
+            let mut cycle = path.split_off(position);
+            cycle.reverse();
+            cycle.push(cycle[0]);
+            return cycle;
+        }
+        path.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(id: &str) -> Migration {
+        Migration {
+            id: id.to_string(),
+            version: id[..5].to_string(),
+            file_path: format!("{}.sh", id).into(),
+            ..Default::default()
+        }
+    }
+
+    fn declared(requires: &[&str], before: &[&str]) -> Declared {
+        Declared {
+            requires: requires.iter().map(|s| s.to_string()).collect(),
+            before: before.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_orders_dependencies_first_then_version() {
+        let migrations = vec![
+            migration("1f700-a"),
+            migration("1f710-b"),
+            migration("1f720-c"),
+            migration("1f730-d"),
+        ];
+        let declared = vec![
+            declared(&["1f720-c"], &[]),
+            declared(&[], &[]),
+            declared(&[], &[]),
+            declared(&[], &["1f720"]),
+        ];
+
+        let ordered = order_by_dependencies(migrations, &declared, None).unwrap();
+        let ids: Vec<&str> = ordered.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["1f710-b", "1f730-d", "1f720-c", "1f700-a"]);
+
+        let c = ordered.iter().find(|m| m.id == "1f720-c").unwrap();
+        assert_eq!(c.requires, vec!["1f730-d"]);
+    }
+
+    #[test]
+    fn test_rejects_cycles() {
+        let migrations = vec![migration("1f700-a"), migration("1f710-b")];
+        let declared = vec![declared(&["1f710-b"], &[]), declared(&["1f700"], &[])];
+
+        let err = order_by_dependencies(migrations, &declared, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("cycle"), "{}", err);
+        assert!(
+            err.contains("1f700-a") && err.contains("1f710-b"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_missing_dependencies() {
+        let declared = vec![declared(&["1f600-gone"], &[])];
+
+        let err = order_by_dependencies(vec![migration("1f700-a")], &declared, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Requires: 1f600-gone"), "{}", err);
+
+        // Covered by the baseline, so it has already been applied
+        let ordered =
+            order_by_dependencies(vec![migration("1f700-a")], &declared, Some("1f650")).unwrap();
+        assert!(ordered[0].requires.is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+
+/// Parse a human-friendly duration such as "30s", "5m", "1h30m" or "500ms".
+/// A bare number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    if s.is_empty() {
+        bail!("Empty duration");
+    }
+
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            bail!("Invalid duration '{}': expected a number", s);
+        }
+        let value: u64 = rest[..digits].parse()?;
+        rest = &rest[digits..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        rest = &rest[unit_len..];
+
+        let seconds_per_unit = match unit {
+            "ms" => None,
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(60 * 60),
+            _ => bail!(
+                "Invalid duration '{}': unknown unit '{}' (use ms, s, m or h)",
+                s,
+                unit
+            ),
+        };
+        let part = match seconds_per_unit {
+            None => Some(Duration::from_millis(value)),
+            Some(factor) => value.checked_mul(factor).map(Duration::from_secs),
+        };
+        total = part
+            .and_then(|part| total.checked_add(part))
+            .with_context(|| format!("Invalid duration '{}': duration too large", s))?;
+    }
+
+    Ok(total)
+}
+
+/// Format a duration in the same notation accepted by `parse_duration`.
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.subsec_millis();
+    let secs = duration.as_secs();
+
+    if secs == 0 {
+        return format!("{}ms", millis);
+    }
+
+    let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    let mut out = String::new();
+    if h > 0 {
+        out.push_str(&format!("{}h", h));
+    }
+    if m > 0 {
+        out.push_str(&format!("{}m", m));
+    }
+    if s > 0 || out.is_empty() {
+        out.push_str(&format!("{}s", s));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("m").is_err());
+
+        // Overflowing units or sums are errors rather than panics or wrapped values
+        let max = u64::MAX;
+        assert!(parse_duration(&format!("{}h", max / 60)).is_err());
+        assert!(parse_duration(&format!("{}s{}s", max, max)).is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(300)), "5m");
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(61)), "1m1s");
+        assert_eq!(format_duration(Duration::from_millis(500)), "500ms");
+    }
+}
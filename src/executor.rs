@@ -1,31 +1,335 @@
 use anyhow::{Context, Result};
-use std::process::Command;
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::{ExecutionContext, ExecutionResult, Migration};
+use crate::declarative::{self, is_declarative};
+use crate::duration::format_duration;
+use crate::logs::RunLog;
+use crate::patch::{self, is_patch};
+use crate::scripting::{self, is_rhai};
+use crate::{Direction, ExecutionContext, ExecutionResult, Migration, Phase};
+
+/// Exit code a migration's check phase uses to report that its target state already exists
+pub const SATISFIED_EXIT_CODE: i32 = 100;
+
+/// How often a migration with a timeout, or its leftover processes, are checked for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a migration's processes get to exit after SIGTERM before they are killed
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How long to keep capturing output after a logged migration has exited
+const OUTPUT_DRAIN_PERIOD: Duration = Duration::from_secs(2);
 
 /// Execute a migration file as a subprocess.
 /// The migration receives context via environment variables.
+/// When the direction is down, the migration's down script is executed instead.
+/// With an interpreter, the script is passed to it as the last argument.
+/// The migration runs in its own process group. Background processes it leaves behind
+/// are stopped once it exits, and with a timeout the whole group is terminated when
+/// time runs out.
+/// With a log path, stdout and stderr are copied into a timestamped run log as well
+/// as to the terminal.
+/// In the check phase, exiting with `SATISFIED_EXIT_CODE` marks the migration as satisfied
+/// and exiting with 0 means it still needs to run.
+/// Declarative (`.migrate.toml`), JSON patch (`.jsonpatch`) and Rhai (`.rhai`) scripts
+/// run in-process instead.
 pub fn execute(migration: &Migration, ctx: &ExecutionContext) -> Result<ExecutionResult> {
-    let status = Command::new(&migration.file_path)
+    let script = match ctx.direction {
+        Direction::Up => &migration.file_path,
+        Direction::Down => migration
+            .down_path
+            .as_ref()
+            .with_context(|| format!("Migration {} has no down script", migration.id))?,
+    };
+    if is_rhai(script) {
+        return execute_in_process(migration, ctx, |log| scripting::run(script, ctx, log));
+    }
+    // Declarative formats have no check phase, so a check always reports that they must run
+    if is_declarative(script) {
+        return execute_in_process(migration, ctx, |log| {
+            if ctx.phase == Phase::Run {
+                declarative::apply(script, ctx, log)?;
+            }
+            Ok(false)
+        });
+    }
+    if is_patch(script) {
+        return execute_in_process(migration, ctx, |log| {
+            if ctx.phase == Phase::Run {
+                patch::apply(script, ctx, log)?;
+            }
+            Ok(false)
+        });
+    }
+
+    let mut command = match &ctx.interpreter {
+        Some(interpreter) => {
+            let mut parts = interpreter.split_whitespace();
+            let mut command = Command::new(parts.next().unwrap_or_default());
+            command.args(parts).arg(script);
+            command
+        }
+        None => Command::new(script),
+    };
+    command
+        .envs(&ctx.env)
         .env("MIGRATE_PROJECT_ROOT", &ctx.project_root)
         .env("MIGRATE_MIGRATIONS_DIR", &ctx.migrations_dir)
         .env("MIGRATE_ID", &ctx.migration_id)
         .env("MIGRATE_DRY_RUN", ctx.dry_run.to_string())
-        .current_dir(&ctx.project_root)
-        .status()
+        .env("MIGRATE_DIRECTION", ctx.direction.as_str())
+        .env("MIGRATE_PHASE", ctx.phase.as_str())
+        .env(
+            "MIGRATE_SATISFIED_EXIT_CODE",
+            SATISFIED_EXIT_CODE.to_string(),
+        )
+        .current_dir(&ctx.project_root);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let log = match &ctx.log_path {
+        Some(path) => {
+            let log = RunLog::create(path)?;
+            log.write_line(
+                "migrate",
+                format!(
+                    "running {} ({}, {})",
+                    migration.id,
+                    ctx.direction.as_str(),
+                    ctx.phase.as_str()
+                )
+                .as_bytes(),
+            )?;
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+            Some(log)
+        }
+        None => None,
+    };
+
+    let mut child = command
+        .spawn()
         .with_context(|| format!("Failed to execute migration: {}", migration.id))?;
 
-    Ok(ExecutionResult {
-        success: status.success(),
-        exit_code: status.code().unwrap_or(-1),
-        error: if status.success() {
-            None
-        } else {
-            Some(format!(
-                "Migration {} failed with exit code {}",
+    let readers = match &log {
+        Some(log) => {
+            let mut readers = Vec::new();
+            if let Some(stdout) = child.stdout.take() {
+                readers.push(log.tee("stdout", stdout, io::stdout()));
+            }
+            if let Some(stderr) = child.stderr.take() {
+                readers.push(log.tee("stderr", stderr, io::stderr()));
+            }
+            readers
+        }
+        None => Vec::new(),
+    };
+
+    let (status, timed_out) = wait(&mut child, ctx.timeout)?;
+    // Leftover background processes would keep the output pipes open
+    stop_group(&mut child)?;
+    finish_readers(readers);
+
+    let result = if timed_out {
+        ExecutionResult {
+            success: false,
+            exit_code: status.code().unwrap_or(-1),
+            error: Some(format!(
+                "Migration {} timed out after {}",
                 migration.id,
-                status.code().unwrap_or(-1)
-            ))
-        },
-    })
+                format_duration(ctx.timeout.unwrap_or_default())
+            )),
+            timed_out: true,
+            satisfied: false,
+        }
+    } else {
+        exit_result(migration, ctx.phase, status)
+    };
+
+    if let Some(log) = &log {
+        let summary = match &result.error {
+            Some(error) => error.clone(),
+            None => format!("exited with code {}", result.exit_code),
+        };
+        log.write_line("migrate", summary.as_bytes())?;
+    }
+
+    Ok(result)
+}
+
+/// Whether a migration script is applied by `migrate` itself rather than executed.
+pub fn runs_in_process(script: &Path) -> bool {
+    is_declarative(script) || is_patch(script) || is_rhai(script)
+}
+
+/// Run a migration that is applied by `migrate` itself rather than by a subprocess.
+/// `apply` returns whether a check phase found the migration already satisfied; an error
+/// fails the migration like a non-zero exit code would.
+fn execute_in_process<F>(
+    migration: &Migration,
+    ctx: &ExecutionContext,
+    apply: F,
+) -> Result<ExecutionResult>
+where
+    F: FnOnce(Option<&RunLog>) -> Result<bool>,
+{
+    let mut result = ExecutionResult {
+        success: true,
+        exit_code: 0,
+        error: None,
+        timed_out: false,
+        satisfied: false,
+    };
+    let log = ctx.log_path.as_deref().map(RunLog::create).transpose()?;
+    if let Some(log) = &log {
+        log.write_line(
+            "migrate",
+            format!(
+                "running {} ({}, {})",
+                migration.id,
+                ctx.direction.as_str(),
+                ctx.phase.as_str()
+            )
+            .as_bytes(),
+        )?;
+    }
+
+    match apply(log.as_ref()) {
+        Ok(satisfied) => result.satisfied = satisfied,
+        Err(err) => {
+            result.success = false;
+            result.exit_code = 1;
+            result.error = Some(match ctx.phase {
+                Phase::Check => format!("Check for migration {} failed: {:#}", migration.id, err),
+                Phase::Run => format!("Migration {} failed: {:#}", migration.id, err),
+            });
+        }
+    }
+
+    if let Some(log) = &log {
+        let summary = match &result.error {
+            Some(error) => error.clone(),
+            None if result.satisfied => "already satisfied".to_string(),
+            None => "completed".to_string(),
+        };
+        log.write_line("migrate", summary.as_bytes())?;
+    }
+
+    Ok(result)
+}
+
+/// Wait for the migration to exit, terminating it once the timeout has passed.
+/// Returns the exit status and whether the migration timed out.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Result<(ExitStatus, bool)> {
+    // A timeout too long to represent can't run out
+    let Some(deadline) = timeout.and_then(|timeout| Instant::now().checked_add(timeout)) else {
+        return Ok((child.wait()?, false));
+    };
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            return Ok((terminate(child)?, true));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Give the output readers a moment to drain once the migration has exited.
+/// Background processes that inherited the pipes can hold them open indefinitely,
+/// so readers still running after the grace period are left behind.
+fn finish_readers(readers: Vec<JoinHandle<()>>) {
+    let deadline = Instant::now() + OUTPUT_DRAIN_PERIOD;
+    while readers.iter().any(|r| !r.is_finished()) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    for reader in readers.into_iter().filter(|r| r.is_finished()) {
+        let _ = reader.join();
+    }
+}
+
+fn exit_result(migration: &Migration, phase: Phase, status: ExitStatus) -> ExecutionResult {
+    let exit_code = status.code().unwrap_or(-1);
+    let satisfied = phase == Phase::Check && exit_code == SATISFIED_EXIT_CODE;
+    let success = status.success() || satisfied;
+
+    let error = match phase {
+        _ if success => None,
+        Phase::Check => Some(format!(
+            "Check for migration {} failed with exit code {}",
+            migration.id, exit_code
+        )),
+        Phase::Run => Some(format!(
+            "Migration {} failed with exit code {}",
+            migration.id, exit_code
+        )),
+    };
+
+    ExecutionResult {
+        success,
+        exit_code,
+        error,
+        timed_out: false,
+        satisfied,
+    }
+}
+
+/// Stop a timed-out migration along with everything else in its process group.
+#[cfg(unix)]
+fn terminate(child: &mut Child) -> Result<ExitStatus> {
+    stop_group(child)?;
+    Ok(child.wait()?)
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) -> Result<ExitStatus> {
+    child.kill()?;
+    Ok(child.wait()?)
+}
+
+/// Stop whatever is still running in a migration's process group: SIGTERM, then SIGKILL
+/// once the grace period is over. Does nothing if the group is already gone.
+#[cfg(unix)]
+fn stop_group(child: &mut Child) -> Result<()> {
+    // The child leads its own process group, so its pid is the group id
+    let pgid = child.id() as libc::pid_t;
+    if !signal_group(pgid, libc::SIGTERM) {
+        return Ok(());
+    }
+
+    let grace_deadline = Instant::now() + KILL_GRACE_PERIOD;
+    loop {
+        // Reap the leader as soon as it exits so it doesn't keep the group alive as a zombie
+        child.try_wait()?;
+        if !signal_group(pgid, 0) {
+            break;
+        }
+        if Instant::now() >= grace_deadline {
+            signal_group(pgid, libc::SIGKILL);
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn stop_group(_child: &mut Child) -> Result<()> {
+    Ok(())
+}
+
+/// Send a signal to every process in a group. Returns false once the group is gone.
+#[cfg(unix)]
+fn signal_group(pgid: libc::pid_t, signal: libc::c_int) -> bool {
+    // SAFETY: kill has no memory safety requirements; a negative pid addresses a group
+    unsafe { libc::kill(-pgid, signal) == 0 }
 }
@@ -0,0 +1,216 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tree::{file_mode, hash_bytes, FileChange};
+
+const JOURNAL_DIR: &str = ".journal";
+const OBJECTS_DIR: &str = "objects";
+const MANIFESTS_DIR: &str = "manifests";
+
+/// Per-migration record of changed files, backed by a content-addressed object store.
+///
+/// Layout under the migrations directory:
+/// - `.journal/objects/<2-char prefix>/<rest of hash>`: file contents keyed by SHA-256
+/// - `.journal/manifests/<id>`: one line per changed file, "<old>[:<mode>] <new> <path>",
+///   with "-" standing in for a missing hash and the old file's octal permissions
+///   recorded where known
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    /// Open the journal for a migrations directory. Nothing is created until written.
+    pub fn open(migrations_dir: &Path) -> Self {
+        Journal {
+            dir: migrations_dir.join(JOURNAL_DIR),
+        }
+    }
+
+    /// Store content in the object store, returning its hash.
+    pub fn store_object(&self, content: &[u8]) -> Result<String> {
+        let hash = hash_bytes(content);
+        let path = self.object_path(&hash);
+
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("Failed to create journal directory: {}", parent.display())
+                })?;
+            }
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write journal object: {}", path.display()))?;
+        }
+
+        Ok(hash)
+    }
+
+    /// Read content from the object store by hash.
+    pub fn read_object(&self, hash: &str) -> Result<Vec<u8>> {
+        let path = self.object_path(hash);
+        fs::read(&path).with_context(|| format!("Journal object not found: {}", hash))
+    }
+
+    /// Record the changes made by a migration.
+    /// Old contents and permissions are read from `before_root`, new contents from `after_root`.
+    pub fn record(
+        &self,
+        id: &str,
+        changes: &[FileChange],
+        before_root: &Path,
+        after_root: &Path,
+    ) -> Result<()> {
+        let mut recorded = Vec::with_capacity(changes.len());
+        for change in changes {
+            let mut change = change.clone();
+            if change.old_hash.is_some() {
+                let before = before_root.join(&change.path);
+                self.store_object(&fs::read(&before)?)?;
+                change.old_mode = file_mode(&before);
+            }
+            if change.new_hash.is_some() {
+                self.store_object(&fs::read(after_root.join(&change.path))?)?;
+            }
+            recorded.push(change);
+        }
+
+        self.write_manifest(id, &recorded)
+    }
+
+    /// Write the change manifest for a migration, replacing any previous one.
+    pub fn write_manifest(&self, id: &str, changes: &[FileChange]) -> Result<()> {
+        let manifests_dir = self.dir.join(MANIFESTS_DIR);
+        fs::create_dir_all(&manifests_dir).with_context(|| {
+            format!(
+                "Failed to create journal directory: {}",
+                manifests_dir.display()
+            )
+        })?;
+
+        let mut content = String::new();
+        for change in changes {
+            let old = match (&change.old_hash, change.old_mode) {
+                (Some(hash), Some(mode)) => format!("{}:{:o}", hash, mode),
+                (Some(hash), None) => hash.clone(),
+                (None, _) => "-".to_string(),
+            };
+            content.push_str(&format!(
+                "{} {} {}\n",
+                old,
+                change.new_hash.as_deref().unwrap_or("-"),
+                change.path.display()
+            ));
+        }
+
+        let path = manifests_dir.join(id);
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write journal manifest: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Read the change manifest for a migration, if one was recorded.
+    pub fn read_manifest(&self, id: &str) -> Result<Option<Vec<FileChange>>> {
+        let path = self.dir.join(MANIFESTS_DIR).join(id);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read journal manifest: {}", path.display()))?;
+
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(parse_manifest_line)
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+    }
+
+    /// Delete the change manifest for a migration. Objects are left in place.
+    pub fn remove_manifest(&self, id: &str) -> Result<()> {
+        let path = self.dir.join(MANIFESTS_DIR).join(id);
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| {
+                format!("Failed to remove journal manifest: {}", path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        let (prefix, rest) = hash.split_at(2.min(hash.len()));
+        self.dir.join(OBJECTS_DIR).join(prefix).join(rest)
+    }
+}
+
+/// Parse one manifest line: "<old>[:<mode>] <new> <path>".
+fn parse_manifest_line(line: &str) -> Result<FileChange> {
+    let parts: Vec<&str> = line.splitn(3, ' ').collect();
+    if parts.len() != 3 {
+        bail!("Invalid line in journal manifest: {}", line);
+    }
+
+    let hash = |s: &str| (s != "-").then(|| s.to_string());
+    let (old, old_mode) = match parts[0].split_once(':') {
+        Some((old, mode)) => match u32::from_str_radix(mode, 8) {
+            Ok(mode) => (old, Some(mode)),
+            Err(_) => bail!("Invalid line in journal manifest: {}", line),
+        },
+        None => (parts[0], None),
+    };
+
+    Ok(FileChange {
+        path: PathBuf::from(parts[2]),
+        old_hash: hash(old),
+        new_hash: hash(parts[1]),
+        old_mode,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path());
+
+        let changes = vec![
+            FileChange {
+                path: PathBuf::from("src/new file.ts"),
+                old_hash: None,
+                new_hash: Some("abc".to_string()),
+                old_mode: None,
+            },
+            FileChange {
+                path: PathBuf::from("old.txt"),
+                old_hash: Some("def".to_string()),
+                new_hash: None,
+                old_mode: None,
+            },
+            FileChange {
+                path: PathBuf::from("bin/run"),
+                old_hash: Some("123".to_string()),
+                new_hash: Some("456".to_string()),
+                old_mode: Some(0o755),
+            },
+        ];
+
+        journal.write_manifest("1f700-first", &changes).unwrap();
+        assert_eq!(journal.read_manifest("1f700-first").unwrap(), Some(changes));
+        assert_eq!(journal.read_manifest("1f710-second").unwrap(), None);
+    }
+
+    #[test]
+    fn test_objects_are_content_addressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path());
+
+        let hash = journal.store_object(b"hello").unwrap();
+        assert_eq!(hash, hash_bytes(b"hello"));
+        assert_eq!(journal.store_object(b"hello").unwrap(), hash);
+        assert_eq!(journal.read_object(&hash).unwrap(), b"hello");
+    }
+}
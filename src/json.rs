@@ -0,0 +1,466 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// Formatting of a JSON file, kept when the file is rewritten
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonStyle {
+    /// Indentation of nested values; None for single-line JSON
+    pub indent: Option<String>,
+    /// Whether the file ends with a newline
+    pub trailing_newline: bool,
+}
+
+impl Default for JsonStyle {
+    fn default() -> Self {
+        JsonStyle {
+            indent: Some("  ".to_string()),
+            trailing_newline: true,
+        }
+    }
+}
+
+impl JsonStyle {
+    /// Detect the style of JSON content from its first indented line.
+    pub fn detect(content: &str) -> Self {
+        let body = content.trim_end();
+        let indent = if body.contains('\n') {
+            let indent = body
+                .lines()
+                .skip(1)
+                .map(|line| &line[..line.len() - line.trim_start().len()])
+                .find(|indent| !indent.is_empty())
+                .unwrap_or("  ");
+            Some(indent.to_string())
+        } else {
+            None
+        };
+
+        JsonStyle {
+            indent,
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    /// Serialize a value in this style.
+    pub fn format(&self, value: &Value) -> Result<String> {
+        let mut out = match &self.indent {
+            Some(indent) => {
+                let mut buffer = Vec::new();
+                let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+                let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+                value.serialize(&mut serializer)?;
+                String::from_utf8(buffer)?
+            }
+            None => serde_json::to_string(value)?,
+        };
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// Read a JSON file along with its formatting.
+pub fn read_file(path: &Path) -> Result<(Value, JsonStyle)> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read JSON file: {}", path.display()))?;
+    let value = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid JSON in {}", path.display()))?;
+    Ok((value, JsonStyle::detect(&content)))
+}
+
+/// Write a JSON file in the given style.
+pub fn write_file(path: &Path, value: &Value, style: &JsonStyle) -> Result<()> {
+    fs::write(path, style.format(value)?)
+        .with_context(|| format!("Failed to write JSON file: {}", path.display()))
+}
+
+/// Split a JSON Pointer (RFC 6901) into its unescaped reference tokens.
+pub fn parse_pointer(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        bail!("Invalid JSON pointer '{}': must start with '/'", pointer);
+    };
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Set the value at a JSON Pointer, replacing any existing value.
+/// With `create_parents`, missing parent objects are created; otherwise the parent must exist.
+/// In arrays, the last token may be an index to insert at or `-` to append.
+pub fn set(root: &mut Value, pointer: &str, value: Value, create_parents: bool) -> Result<()> {
+    let tokens = parse_pointer(pointer)?;
+    let Some((last, parents)) = tokens.split_last() else {
+        *root = value;
+        return Ok(());
+    };
+
+    let mut current = root;
+    for token in parents {
+        current = match current {
+            Value::Object(map) => {
+                if create_parents && !map.contains_key(token) {
+                    map.insert(token.clone(), Value::Object(Map::new()));
+                }
+                map.get_mut(token).with_context(|| {
+                    format!("Path '{}' does not exist (missing '{}')", pointer, token)
+                })?
+            }
+            Value::Array(items) => {
+                let index = array_index(token, items.len(), pointer)?;
+                &mut items[index]
+            }
+            _ => bail!(
+                "Path '{}' goes through a value that is not a container",
+                pointer
+            ),
+        };
+    }
+
+    match current {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => {
+            let index = array_index(last, items.len() + 1, pointer)?;
+            items.insert(index, value);
+        }
+        _ => bail!(
+            "Path '{}' goes through a value that is not a container",
+            pointer
+        ),
+    }
+    Ok(())
+}
+
+/// Remove and return the value at a JSON Pointer, or None if there is nothing there.
+pub fn remove(root: &mut Value, pointer: &str) -> Result<Option<Value>> {
+    let tokens = parse_pointer(pointer)?;
+    let Some((last, parents)) = tokens.split_last() else {
+        bail!("Cannot remove the whole document");
+    };
+
+    let mut current = root;
+    for token in parents {
+        let next = match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => token.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(match current {
+        Value::Object(map) => map.shift_remove(last),
+        Value::Array(items) => match last.parse::<usize>() {
+            Ok(index) if index < items.len() => Some(items.remove(index)),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Apply a JSON Patch (RFC 6902). The patch is applied to a copy, so the document is
+/// left untouched when an operation fails, including a `test` that doesn't match.
+pub fn apply_patch(root: &mut Value, patch: &[Value]) -> Result<()> {
+    let mut patched = root.clone();
+    for (i, operation) in patch.iter().enumerate() {
+        apply_operation(&mut patched, operation)
+            .with_context(|| format!("Patch operation {} failed", i + 1))?;
+    }
+    *root = patched;
+    Ok(())
+}
+
+fn apply_operation(root: &mut Value, operation: &Value) -> Result<()> {
+    let field = |name: &str| {
+        operation
+            .get(name)
+            .with_context(|| format!("Missing '{}' in {}", name, operation))
+    };
+    let pointer = |name: &str| -> Result<&str> {
+        field(name)?
+            .as_str()
+            .with_context(|| format!("'{}' must be a string in {}", name, operation))
+    };
+
+    let op = pointer("op")?;
+    let path = pointer("path")?;
+    parse_pointer(path)?;
+    match op {
+        "add" => set(root, path, field("value")?.clone(), false),
+        "remove" => match remove(root, path)? {
+            Some(_) => Ok(()),
+            None => bail!("Path '{}' does not exist", path),
+        },
+        "replace" => {
+            let target = root
+                .pointer_mut(path)
+                .with_context(|| format!("Path '{}' does not exist", path))?;
+            *target = field("value")?.clone();
+            Ok(())
+        }
+        "move" => {
+            let from = pointer("from")?;
+            if path.starts_with(&format!("{}/", from)) {
+                bail!("Cannot move '{}' into its own child '{}'", from, path);
+            }
+            let value =
+                remove(root, from)?.with_context(|| format!("Path '{}' does not exist", from))?;
+            set(root, path, value, false)
+        }
+        "copy" => {
+            let from = pointer("from")?;
+            let value = root
+                .pointer(from)
+                .with_context(|| format!("Path '{}' does not exist", from))?
+                .clone();
+            set(root, path, value, false)
+        }
+        "test" => {
+            let expected = field("value")?;
+            match root.pointer(path) {
+                Some(actual) if values_equal(actual, expected) => Ok(()),
+                Some(actual) => bail!(
+                    "Test failed: '{}' is {}, expected {}",
+                    path,
+                    actual,
+                    expected
+                ),
+                None => bail!("Test failed: '{}' does not exist", path),
+            }
+        }
+        _ => bail!("Unknown patch operation '{}'", op),
+    }
+}
+
+/// Apply a JSON Merge Patch (RFC 7386): objects are merged recursively, `null` removes
+/// a member, and anything else replaces the target. New members are added at the end.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(members) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+    for (key, value) in members {
+        if value.is_null() {
+            target.shift_remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Order the members of objects in `value` like those of `original`: members that were
+/// already there keep their place, and new ones follow in their own order.
+pub fn keep_order(value: &mut Value, original: &Value) {
+    match (value, original) {
+        (Value::Object(members), Value::Object(original_members)) => {
+            let mut ordered = Map::new();
+            for key in original_members.keys() {
+                if let Some(member) = members.shift_remove(key) {
+                    ordered.insert(key.clone(), member);
+                }
+            }
+            ordered.append(members);
+            *members = ordered;
+            for (key, member) in members.iter_mut() {
+                if let Some(original_member) = original_members.get(key) {
+                    keep_order(member, original_member);
+                }
+            }
+        }
+        (Value::Array(items), Value::Array(original_items)) => {
+            for (item, original_item) in items.iter_mut().zip(original_items) {
+                keep_order(item, original_item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compare values as RFC 6902 `test` does: numbers are equal when their values are,
+/// so `1` matches `1.0`, at any depth.
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+                a == b
+            } else if let (Some(a), Some(b)) = (a.as_u64(), b.as_u64()) {
+                a == b
+            } else {
+                a.as_f64() == b.as_f64()
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| values_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| values_equal(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+fn array_index(token: &str, len: usize, pointer: &str) -> Result<usize> {
+    match token.parse::<usize>() {
+        Ok(index) if index < len => Ok(index),
+        _ => bail!("Path '{}' has an invalid array index '{}'", pointer, token),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_style_roundtrip() {
+        let content = "{\n    \"b\": 1,\n    \"a\": [\n        true\n    ]\n}\n";
+        let style = JsonStyle::detect(content);
+        assert_eq!(style.indent.as_deref(), Some("    "));
+
+        // Key order is kept
+        let value: Value = serde_json::from_str(content).unwrap();
+        assert_eq!(style.format(&value).unwrap(), content);
+
+        let compact = JsonStyle::detect("{\"a\":1}");
+        assert_eq!(compact.format(&json!({"a": 1})).unwrap(), "{\"a\":1}");
+    }
+
+    #[test]
+    fn test_set_and_remove() {
+        let mut value = json!({"scripts": {"build": "tsc"}, "files": ["a"]});
+
+        set(&mut value, "/scripts/lint", json!("eslint ."), false).unwrap();
+        set(&mut value, "/files/-", json!("b"), false).unwrap();
+        set(&mut value, "/compilerOptions/strict", json!(true), true).unwrap();
+        assert!(set(&mut value, "/missing/key", json!(1), false).is_err());
+        set(&mut value, "/a~1b", json!(1), false).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "scripts": {"build": "tsc", "lint": "eslint ."},
+                "files": ["a", "b"],
+                "compilerOptions": {"strict": true},
+                "a/b": 1
+            })
+        );
+
+        assert_eq!(
+            remove(&mut value, "/scripts/build").unwrap(),
+            Some(json!("tsc"))
+        );
+        assert_eq!(remove(&mut value, "/files/0").unwrap(), Some(json!("a")));
+        assert_eq!(remove(&mut value, "/nope/deeper").unwrap(), None);
+        assert!(parse_pointer("no-slash").is_err());
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let mut value = json!({"name": "app", "scripts": {"lint": "eslint ."}, "tags": ["a"]});
+
+        apply_patch(
+            &mut value,
+            json!([
+                {"op": "test", "path": "/name", "value": "app"},
+                {"op": "add", "path": "/scripts/format", "value": "prettier ."},
+                {"op": "move", "from": "/scripts/lint", "path": "/scripts/check"},
+                {"op": "copy", "from": "/tags/0", "path": "/tags/-"},
+                {"op": "replace", "path": "/name", "value": "web"},
+                {"op": "remove", "path": "/tags/0"}
+            ])
+            .as_array()
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({"name": "web", "scripts": {"format": "prettier .", "check": "eslint ."}, "tags": ["a"]})
+        );
+
+        // A failing operation leaves the document as it was
+        let before = value.clone();
+        let err = apply_patch(
+            &mut value,
+            json!([
+                {"op": "remove", "path": "/name"},
+                {"op": "test", "path": "/tags/0", "value": "b"}
+            ])
+            .as_array()
+            .unwrap(),
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("Test failed"), "{:#}", err);
+        assert_eq!(value, before);
+
+        assert!(apply_patch(&mut value, &[json!({"op": "remove", "path": "/nope"})]).is_err());
+
+        // Numbers compare by value, including inside arrays and objects
+        let mut value = json!({"n": 1, "nested": {"list": [2, {"x": 3}]}});
+        apply_patch(
+            &mut value,
+            json!([
+                {"op": "test", "path": "/n", "value": 1.0},
+                {"op": "test", "path": "/nested", "value": {"list": [2.0, {"x": 3.0}]}}
+            ])
+            .as_array()
+            .unwrap(),
+        )
+        .unwrap();
+        let err = apply_patch(
+            &mut value,
+            &[json!({"op": "test", "path": "/n", "value": 1.5})],
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("Test failed"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut value = json!({"a": "b", "c": {"d": "e", "f": "g"}, "z": 1});
+        merge_patch(&mut value, &json!({"a": "z", "c": {"f": null}, "n": [1]}));
+        assert_eq!(value, json!({"a": "z", "c": {"d": "e"}, "z": 1, "n": [1]}));
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            "{\"a\":\"z\",\"c\":{\"d\":\"e\"},\"z\":1,\"n\":[1]}"
+        );
+
+        merge_patch(&mut value, &json!(["replaced"]));
+        assert_eq!(value, json!(["replaced"]));
+    }
+
+    #[test]
+    fn test_keep_order() {
+        let original = json!({"name": "app", "scripts": {"z": "1", "a": "2"}});
+        let mut value: Value = serde_json::from_str(
+            r#"{"added": true, "name": "web", "scripts": {"a": "2", "b": "3", "z": "1"}}"#,
+        )
+        .unwrap();
+        keep_order(&mut value, &original);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"name":"web","scripts":{"z":"1","a":"2","b":"3"},"added":true}"#
+        );
+    }
+}
@@ -1,32 +1,163 @@
 pub mod baseline;
 pub mod commands;
+pub mod config;
+pub mod declarative;
+pub mod dependencies;
+pub mod duration;
 pub mod executor;
+pub mod host;
+pub mod journal;
+pub mod json;
 pub mod loader;
+pub mod lock;
+pub mod logs;
+pub mod patch;
+pub mod render;
+pub mod scripting;
+pub mod selection;
+pub mod snapshot;
 pub mod state;
 pub mod templates;
+pub mod tree;
 pub mod version;
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// Metadata for a discovered migration file
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Migration {
     /// Migration ID (e.g., "1f72f-init")
     pub id: String,
     /// Version string (e.g., "1f72f")
     pub version: String,
-    /// Absolute path to the migration file
+    /// Absolute path to the migration file (the entry point of a directory migration)
     pub file_path: PathBuf,
+    /// Directory holding a directory migration's scripts and assets
+    pub dir: Option<PathBuf>,
+    /// Absolute path to the paired down script, if the migration is reversible
+    pub down_path: Option<PathBuf>,
+    /// Maximum run time from the migration's `Timeout:` header
+    pub timeout: Option<Duration>,
+    /// IDs of migrations that must be applied before this one, from its `Requires:`
+    /// header and other migrations' `Before:` headers
+    pub requires: Vec<String>,
+    /// Header metadata (e.g., `# Description: Add config`), keyed by lowercase name
+    pub metadata: BTreeMap<String, String>,
+    /// Whether the migration has a check phase, from its `Check:` header
+    pub check: bool,
 }
 
-/// Record of an applied migration
-#[derive(Debug, Clone)]
+impl Migration {
+    /// Whether this migration has a down script and can be reverted
+    pub fn is_reversible(&self) -> bool {
+        self.down_path.is_some()
+    }
+
+    /// Description from the migration's `Description:` header
+    pub fn description(&self) -> Option<&str> {
+        self.metadata
+            .get("description")
+            .map(|d| d.as_str())
+            .filter(|d| !d.is_empty())
+    }
+
+    /// Tags from the migration's `Tags:` header
+    pub fn tags(&self) -> Vec<String> {
+        self.metadata
+            .get("tags")
+            .map(|tags| loader::split_list(tags))
+            .unwrap_or_default()
+    }
+
+    /// Content hash of the migration file, or of all files of a directory migration
+    pub fn checksum(&self) -> anyhow::Result<String> {
+        match &self.dir {
+            Some(dir) => tree::hash_dir(dir),
+            None => tree::hash_file(&self.file_path),
+        }
+    }
+}
+
+/// Record of an applied migration.
+/// Fields other than the ID and timestamp are absent in entries from older history formats.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct AppliedMigration {
     /// Migration ID
     pub id: String,
     /// When the migration was applied
     pub applied_at: DateTime<Utc>,
+    /// Content hash of the migration file when it was applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// How long the migration took to run, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Exit code of the migration process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Version of migrate that applied the migration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migrate_version: Option<String>,
+    /// Machine the migration was applied on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// User who applied the migration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Interpreter from the migration's shebang line (e.g., "bash", "npx tsx")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    /// Description from the migration's header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Tag filter that was active when the migration was applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_filter: Option<selection::TagFilter>,
+    /// Whether the migration's check phase found it already satisfied, so it never ran
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub applied_by_check: bool,
+}
+
+/// Direction in which a migration is executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Apply the migration
+    Up,
+    /// Revert the migration using its down script
+    Down,
+}
+
+impl Direction {
+    /// Value passed to migrations via MIGRATE_DIRECTION
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        }
+    }
+}
+
+/// Phase of a migration run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Ask the migration whether its target state already exists
+    Check,
+    /// Make the migration's changes
+    Run,
+}
+
+impl Phase {
+    /// Value passed to migrations via MIGRATE_PHASE
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Check => "check",
+            Phase::Run => "run",
+        }
+    }
 }
 
 /// Execution context passed via environment variables
@@ -40,6 +171,18 @@ pub struct ExecutionContext {
     pub migration_id: String,
     /// Whether this is a dry run
     pub dry_run: bool,
+    /// Whether the migration is being applied or reverted
+    pub direction: Direction,
+    /// Whether this is the check phase or the real run
+    pub phase: Phase,
+    /// Kill the migration if it runs longer than this
+    pub timeout: Option<Duration>,
+    /// File to capture the migration's output in, if any
+    pub log_path: Option<PathBuf>,
+    /// Extra environment variables for the migration process
+    pub env: BTreeMap<String, String>,
+    /// Command to run the script with, instead of executing the script itself
+    pub interpreter: Option<String>,
 }
 
 /// Result of executing a migration
@@ -51,4 +194,8 @@ pub struct ExecutionResult {
     pub exit_code: i32,
     /// Error message if any
     pub error: Option<String>,
+    /// Whether the migration was killed for exceeding its timeout
+    pub timed_out: bool,
+    /// Whether a check phase reported the migration as already satisfied
+    pub satisfied: bool,
 }
@@ -1,12 +1,29 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use glob::glob;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 
+use crate::baseline::read_baseline;
+use crate::declarative::EXTENSION as DECLARATIVE_EXTENSION;
+use crate::dependencies::{order_by_dependencies, Declared};
+use crate::duration::parse_duration;
 use crate::version::is_valid_version;
 use crate::Migration;
 
+/// Suffix of the fixture directories used by `migrate test` (`<id>.test`)
+pub const FIXTURE_SUFFIX: &str = ".test";
+
 /// Discover all migrations in the given directory.
-/// Migrations must match the pattern XXXXX-name.ext where XXXXX is a 5-char base36 version
+/// Migrations must match the pattern XXXXX-name.ext where XXXXX is a 5-char base36 version.
+/// Files named XXXXX-name.down.ext are paired with their migration as its down script.
+/// A directory XXXXX-name/ is a migration too, with a `run` or `run.<ext>` entry point
+/// and optionally a `down` or `down.<ext>` script, unless it holds the assets of a
+/// migration file with the same ID. A directory at or before the baseline without an
+/// entry point is the leftover asset directory of a deleted migration and is skipped.
+/// Migrations are ordered by version, except that `Requires:` and `Before:` headers
+/// move a migration after the migrations it depends on.
 pub fn discover_migrations(dir: &Path) -> Result<Vec<Migration>> {
     // Match 5 alphanumeric characters followed by dash
     let pattern = dir.join("[0-9a-z][0-9a-z][0-9a-z][0-9a-z][0-9a-z]-*");
@@ -14,26 +31,149 @@ pub fn discover_migrations(dir: &Path) -> Result<Vec<Migration>> {
         .to_str()
         .context("Invalid path for migration directory")?;
 
-    let mut migrations: Vec<Migration> = glob(pattern_str)
+    let paths: Vec<PathBuf> = glob(pattern_str)
         .context("Failed to read glob pattern")?
         .filter_map(|entry| entry.ok())
-        .filter(|path| path.is_file())
-        .filter_map(|path| {
-            let filename = path.file_name()?.to_str()?;
-            let version = extract_version(filename)?;
+        .filter(|path| path.is_file() || path.is_dir())
+        .collect();
+
+    // Collect down scripts first so they can be attached to their migrations,
+    // and file IDs so asset directories aren't mistaken for migrations
+    let mut down_scripts: HashMap<String, PathBuf> = HashMap::new();
+    let mut file_ids: HashSet<String> = HashSet::new();
+    for path in paths.iter().filter(|p| p.is_file()) {
+        let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        match extract_down_id(filename) {
+            Some(id) => {
+                down_scripts.insert(id, path.clone());
+            }
+            None => {
+                file_ids.insert(extract_id(filename));
+            }
+        }
+    }
+
+    let baseline = read_baseline(dir)?;
+    let baseline_version = baseline.as_ref().map(|b| b.version.as_str());
+
+    let mut migrations: Vec<(Migration, Declared)> = Vec::new();
+    for path in paths {
+        let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        let Some(version) = extract_version(filename) else {
+            continue;
+        };
+
+        let (id, file_path, down_path, migration_dir) = if path.is_dir() {
+            let id = filename.to_string();
+            if id.ends_with(FIXTURE_SUFFIX) || file_ids.contains(&id) {
+                continue;
+            }
+            let Some(entry_point) = find_entry_point(&path, "run")? else {
+                // Asset directories of baselined migrations may have outlived their file
+                if baseline_version.is_some_and(|b| version.as_str() <= b) {
+                    continue;
+                }
+                bail!(
+                    "Migration directory {} has no entry point (run, run.sh, run.ts, ...)",
+                    path.display()
+                );
+            };
+            let down_path = find_entry_point(&path, "down")?;
+            (id, entry_point, down_path, Some(path))
+        } else {
+            if extract_down_id(filename).is_some() {
+                continue;
+            }
             let id = extract_id(filename);
-            Some(Migration {
-                id,
-                version,
-                file_path: path,
+            let down_path = down_scripts.remove(&id);
+            (id, path, down_path, None)
+        };
+
+        let metadata = read_headers(&file_path);
+        let timeout = metadata
+            .get("timeout")
+            .map(|value| {
+                parse_duration(value)
+                    .with_context(|| format!("Invalid Timeout header in migration {}", id))
+            })
+            .transpose()?;
+
+        let check = metadata
+            .get("check")
+            .map(|value| {
+                parse_bool(value)
+                    .with_context(|| format!("Invalid Check header in migration {}", id))
             })
+            .transpose()?
+            .unwrap_or(false);
+
+        let declared = Declared {
+            requires: metadata
+                .get("requires")
+                .map(|v| split_list(v))
+                .unwrap_or_default(),
+            before: metadata
+                .get("before")
+                .map(|v| split_list(v))
+                .unwrap_or_default(),
+        };
+
+        let migration = Migration {
+            id,
+            version,
+            file_path,
+            dir: migration_dir,
+            down_path,
+            timeout,
+            requires: Vec::new(),
+            metadata,
+            check,
+        };
+        migrations.push((migration, declared));
+    }
+
+    // Sort by version string (lexicographic sort works for base36),
+    // then move migrations after the ones they depend on
+    migrations.sort_by(|(a, _), (b, _)| a.version.cmp(&b.version));
+    let (migrations, declared): (Vec<Migration>, Vec<Declared>) = migrations.into_iter().unzip();
+
+    order_by_dependencies(migrations, &declared, baseline_version)
+}
+
+/// Find the script named `stem` or `stem.<ext>` in a directory migration.
+fn find_entry_point(dir: &Path, stem: &str) -> Result<Option<PathBuf>> {
+    let prefix = format!("{}.", stem);
+    let mut found: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read migration directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|f| f.to_str())
+                .is_some_and(|name| name == stem || name.starts_with(&prefix))
         })
         .collect();
+    found.sort();
 
-    // Sort by version string (lexicographic sort works for base36)
-    migrations.sort_by(|a, b| a.version.cmp(&b.version));
-
-    Ok(migrations)
+    if found.len() > 1 {
+        let names: Vec<String> = found
+            .iter()
+            .filter_map(|p| p.file_name())
+            .map(|f| f.to_string_lossy().into_owned())
+            .collect();
+        bail!(
+            "Migration directory {} has several '{}' scripts: {}",
+            dir.display(),
+            stem,
+            names.join(", ")
+        );
+    }
+    Ok(found.pop())
 }
 
 /// Extract the version from a migration filename.
@@ -55,8 +195,12 @@ pub fn extract_version(filename: &str) -> Option<String> {
 }
 
 /// Extract the migration ID from a filename.
-/// The ID is the filename without extension (e.g., "1f72f-init" from "1f72f-init.sh")
+/// The ID is the filename without extension (e.g., "1f72f-init" from "1f72f-init.sh"
+/// or "1fb2g-prettier" from "1fb2g-prettier.migrate.toml")
 pub fn extract_id(filename: &str) -> String {
+    if let Some(id) = filename.strip_suffix(DECLARATIVE_EXTENSION) {
+        return id.to_string();
+    }
     // Remove extension if present
     match filename.rfind('.') {
         Some(pos) => filename[..pos].to_string(),
@@ -64,6 +208,115 @@ pub fn extract_id(filename: &str) -> String {
     }
 }
 
+/// Read the interpreter from a migration's shebang line.
+/// `/usr/bin/env` and its `-S` flag are dropped (e.g., "npx tsx" from "#!/usr/bin/env -S npx tsx").
+pub fn read_interpreter(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let mut first_line = String::new();
+    BufReader::new(file).read_line(&mut first_line).ok()?;
+    parse_shebang(&first_line)
+}
+
+/// Parse the interpreter command out of a shebang line.
+fn parse_shebang(line: &str) -> Option<String> {
+    let mut parts = line.strip_prefix("#!")?.split_whitespace().peekable();
+
+    if parts.peek().is_some_and(|p| p.ends_with("/env")) {
+        parts.next();
+        if parts.peek() == Some(&"-S") {
+            parts.next();
+        }
+    }
+
+    let interpreter = parts.collect::<Vec<_>>().join(" ");
+    (!interpreter.is_empty()).then_some(interpreter)
+}
+
+/// Read the header block of a migration file.
+/// Headers are `Key: value` comments (`#` or `//`) at the top of the file, ending at the
+/// first blank line, e.g. `# Description: Add config` or `// Timeout: 5m`.
+/// Keys are lowercased; values of repeated keys are joined with ", ".
+pub fn read_headers(path: &Path) -> BTreeMap<String, String> {
+    let Ok(file) = File::open(path) else {
+        return BTreeMap::new();
+    };
+    let lines: Vec<String> = BufReader::new(file)
+        .lines()
+        .take(MAX_HEADER_LINES)
+        .map_while(|line| line.ok())
+        .collect();
+    parse_headers(&lines.join("\n"))
+}
+
+/// Split a list-valued header (e.g., `Requires: 1fa00-a, 1fb00-b`) into its items.
+/// Items may be separated by commas or spaces.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split([',', ' '])
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+/// Parse a yes/no header value (e.g., `Check: true`).
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => bail!("expected true or false, got '{}'", value),
+    }
+}
+
+/// Maximum number of lines scanned for headers
+const MAX_HEADER_LINES: usize = 30;
+
+fn parse_headers(content: &str) -> BTreeMap<String, String> {
+    let mut headers: BTreeMap<String, String> = BTreeMap::new();
+
+    for line in content.lines().take(MAX_HEADER_LINES) {
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("#!") {
+            continue;
+        }
+
+        let Some(comment) = line.strip_prefix("//").or_else(|| line.strip_prefix('#')) else {
+            continue;
+        };
+        let Some((key, value)) = comment.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            continue;
+        }
+
+        let value = value.trim();
+        headers
+            .entry(key.to_ascii_lowercase())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    headers
+}
+
+/// Extract the migration ID from a down script filename.
+/// Returns None if the file is not a down script (e.g., "1f72f-init" from "1f72f-init.down.sh")
+pub fn extract_down_id(filename: &str) -> Option<String> {
+    extract_version(filename)?;
+    if let Some(id) = extract_id(filename).strip_suffix(".down") {
+        return Some(id.to_string());
+    }
+    filename.strip_suffix(".down").map(|id| id.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,5 +339,133 @@ mod tests {
         assert_eq!(extract_id("1f72f-init.sh"), "1f72f-init");
         assert_eq!(extract_id("00000-add-config.ts"), "00000-add-config");
         assert_eq!(extract_id("zzzzz-no-extension"), "zzzzz-no-extension");
+        assert_eq!(extract_id("1fb2g-prettier.migrate.toml"), "1fb2g-prettier");
+    }
+
+    #[test]
+    fn test_extract_down_id() {
+        assert_eq!(
+            extract_down_id("1f72f-init.down.sh"),
+            Some("1f72f-init".to_string())
+        );
+        assert_eq!(
+            extract_down_id("1f72f-init.down"),
+            Some("1f72f-init".to_string())
+        );
+        assert_eq!(
+            extract_down_id("1fb2g-prettier.down.migrate.toml"),
+            Some("1fb2g-prettier".to_string())
+        );
+        assert_eq!(extract_down_id("1f72f-init.sh"), None);
+        assert_eq!(extract_down_id("1f72f-countdown.sh"), None);
+        assert_eq!(extract_down_id("ab.down.sh"), None); // Not a migration name
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(
+            parse_shebang("#!/usr/bin/env bash\n"),
+            Some("bash".to_string())
+        );
+        assert_eq!(
+            parse_shebang("#!/usr/bin/env -S npx tsx\n"),
+            Some("npx tsx".to_string())
+        );
+        assert_eq!(
+            parse_shebang("#!/bin/sh -e"),
+            Some("/bin/sh -e".to_string())
+        );
+        assert_eq!(parse_shebang("#!"), None);
+        assert_eq!(parse_shebang("echo hi"), None);
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let content = "#!/usr/bin/env bash\nset -euo pipefail\n# Description: Add config\n# Timeout: 5m\n# Requires: 1f700-a\n# requires: 1f710-b\n\n# Note: not a header\n";
+        let headers = parse_headers(content);
+        assert_eq!(
+            headers.get("description").map(|s| s.as_str()),
+            Some("Add config")
+        );
+        assert_eq!(headers.get("timeout").map(|s| s.as_str()), Some("5m"));
+        assert_eq!(
+            headers.get("requires").map(|s| s.as_str()),
+            Some("1f700-a, 1f710-b")
+        );
+        assert_eq!(headers.get("note"), None);
+
+        let headers = parse_headers("#!/usr/bin/env node\n// Timeout: 30s\n");
+        assert_eq!(headers.get("timeout").map(|s| s.as_str()), Some("30s"));
+    }
+
+    #[test]
+    fn test_split_list() {
+        assert_eq!(split_list("1f700-a, 1f710-b"), vec!["1f700-a", "1f710-b"]);
+        assert_eq!(split_list("a b,,c"), vec!["a", "b", "c"]);
+        assert!(split_list("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        assert!(parse_bool("Yes").unwrap());
+        assert!(!parse_bool("false").unwrap());
+        assert!(parse_bool("maybe").is_err());
+    }
+
+    #[test]
+    fn test_discover_pairs_down_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("1f700-first.sh"), "").unwrap();
+        std::fs::write(dir.path().join("1f700-first.down.sh"), "").unwrap();
+        std::fs::write(dir.path().join("1f710-second.sh"), "").unwrap();
+
+        let migrations = discover_migrations(dir.path()).unwrap();
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(migrations[0].id, "1f700-first");
+        assert_eq!(
+            migrations[0].down_path,
+            Some(dir.path().join("1f700-first.down.sh"))
+        );
+        assert_eq!(migrations[1].id, "1f710-second");
+        assert!(migrations[1].down_path.is_none());
+    }
+
+    #[test]
+    fn test_discover_directory_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        // A directory migration with a down script
+        fs::create_dir_all(root.join("1f700-bundle/assets")).unwrap();
+        fs::write(root.join("1f700-bundle/run.sh"), "# Description: Bundle\n").unwrap();
+        fs::write(root.join("1f700-bundle/down.sh"), "").unwrap();
+        fs::write(root.join("1f700-bundle/assets/config.json"), "{}").unwrap();
+
+        // Assets of a file migration, and a test fixture
+        fs::write(root.join("1f710-file.sh"), "").unwrap();
+        fs::create_dir(root.join("1f710-file")).unwrap();
+        fs::create_dir(root.join("1f710-file.test")).unwrap();
+
+        let migrations = discover_migrations(root).unwrap();
+        assert_eq!(migrations.len(), 2);
+        assert_eq!(migrations[0].id, "1f700-bundle");
+        assert_eq!(migrations[0].file_path, root.join("1f700-bundle/run.sh"));
+        assert_eq!(
+            migrations[0].down_path,
+            Some(root.join("1f700-bundle/down.sh"))
+        );
+        assert_eq!(migrations[0].description(), Some("Bundle"));
+        assert_eq!(migrations[1].id, "1f710-file");
+        assert!(migrations[1].dir.is_none());
+
+        // Changing an asset changes the checksum
+        let before = migrations[0].checksum().unwrap();
+        fs::write(root.join("1f700-bundle/assets/config.json"), "{\"a\": 1}").unwrap();
+        assert_ne!(migrations[0].checksum().unwrap(), before);
+
+        // A directory without an entry point is an error
+        fs::create_dir(root.join("1f720-empty")).unwrap();
+        let err = discover_migrations(root).unwrap_err().to_string();
+        assert!(err.contains("no entry point"), "{}", err);
     }
 }
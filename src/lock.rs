@@ -0,0 +1,248 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::duration::format_duration;
+use crate::host;
+
+const LOCK_FILE: &str = ".lock";
+
+/// How often a held lock is retried while waiting for it
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A lock file that is still unreadable after this long was abandoned mid-write
+const INCOMPLETE_LOCK_AGE: Duration = Duration::from_secs(10);
+
+/// Owner recorded in the lock file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockOwner {
+    /// Process ID of the migrate run holding the lock
+    pub pid: u32,
+    /// Machine the process runs on
+    pub hostname: Option<String>,
+    /// When the lock was acquired
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        LockOwner {
+            pid: std::process::id(),
+            hostname: host::hostname(),
+            acquired_at: Utc::now(),
+        }
+    }
+
+    /// Whether the owning process is known to be gone.
+    /// Only processes on this machine can be checked; locks held elsewhere are never stale.
+    fn is_stale(&self) -> bool {
+        if self.hostname != host::hostname() {
+            return false;
+        }
+        !process_alive(self.pid)
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "pid {} on {} since {}",
+            self.pid,
+            self.hostname.as_deref().unwrap_or("unknown host"),
+            self.acquired_at.to_rfc3339()
+        )
+    }
+}
+
+/// Advisory lock on a migrations directory, held for the duration of a command that
+/// changes history. The lock file is removed when the lock is dropped.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Acquire the lock, waiting up to `wait` for another run to release it.
+    /// Locks left behind by processes that no longer exist are removed.
+    pub fn acquire(migrations_dir: &Path, wait: Option<Duration>) -> Result<Lock> {
+        let path = migrations_dir.join(LOCK_FILE);
+        // A wait too long to represent means waiting for as long as it takes
+        let deadline = Instant::now().checked_add(wait.unwrap_or_default());
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let lock = Lock { path };
+                    file.write_all(format_owner(&LockOwner::current()).as_bytes())
+                        .with_context(|| {
+                            format!("Failed to write lock file: {}", lock.path.display())
+                        })?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create lock file: {}", path.display()))
+                }
+            }
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                // Released between our attempt and the read
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to read lock file: {}", path.display()))
+                }
+            };
+
+            let owner = parse_owner(&content);
+            let stale = match &owner {
+                Some(owner) => owner.is_stale(),
+                None => lock_age(&path).is_some_and(|age| age > INCOMPLETE_LOCK_AGE),
+            };
+
+            if stale {
+                // Only remove the lock we inspected, not one a competing run just created
+                if fs::read_to_string(&path).ok().as_deref() == Some(content.as_str()) {
+                    match &owner {
+                        Some(owner) => {
+                            println!("Removing stale lock held by {}", owner.describe())
+                        }
+                        None => println!("Removing incomplete lock file"),
+                    }
+                    let _ = fs::remove_file(&path);
+                }
+                continue;
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let holder = owner
+                    .map(|o| o.describe())
+                    .unwrap_or_else(|| "another process".to_string());
+                match wait {
+                    Some(wait) => bail!(
+                        "Migrations are locked by {} (waited {}). Remove {} if that run is gone.",
+                        holder,
+                        format_duration(wait),
+                        path.display()
+                    ),
+                    None => bail!(
+                        "Migrations are locked by {}. Use --wait to wait for it, or remove {} if that run is gone.",
+                        holder,
+                        path.display()
+                    ),
+                }
+            }
+
+            thread::sleep(RETRY_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn format_owner(owner: &LockOwner) -> String {
+    let mut content = format!("pid: {}\n", owner.pid);
+    if let Some(hostname) = &owner.hostname {
+        content.push_str(&format!("host: {}\n", hostname));
+    }
+    content.push_str(&format!("acquired: {}\n", owner.acquired_at.to_rfc3339()));
+    content
+}
+
+fn parse_owner(content: &str) -> Option<LockOwner> {
+    let mut pid = None;
+    let mut hostname = None;
+    let mut acquired_at = None;
+
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("pid:") {
+            pid = value.trim().parse().ok();
+        } else if let Some(value) = line.strip_prefix("host:") {
+            hostname = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("acquired:") {
+            acquired_at = DateTime::parse_from_rfc3339(value.trim())
+                .ok()
+                .map(|t| t.with_timezone(&Utc));
+        }
+    }
+
+    Some(LockOwner {
+        pid: pid?,
+        hostname,
+        acquired_at: acquired_at?,
+    })
+}
+
+fn lock_age(path: &Path) -> Option<Duration> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owner_roundtrip() {
+        let owner = LockOwner::current();
+        let parsed = parse_owner(&format_owner(&owner)).unwrap();
+        assert_eq!(parsed.pid, owner.pid);
+        assert_eq!(parsed.hostname, owner.hostname);
+        assert!(parse_owner("").is_none());
+        assert!(parse_owner("pid: 12\n").is_none());
+    }
+
+    #[test]
+    fn test_lock_is_exclusive_and_released_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let lock = Lock::acquire(dir.path(), None).unwrap();
+        let err = Lock::acquire(dir.path(), Some(Duration::from_millis(200))).unwrap_err();
+        assert!(err.to_string().contains("locked by"));
+
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+        Lock::acquire(dir.path(), None).unwrap();
+
+        // An unrepresentable wait doesn't overflow
+        Lock::acquire(dir.path(), Some(Duration::MAX)).unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // A process on this host that no longer exists
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+
+        let owner = LockOwner {
+            pid,
+            ..LockOwner::current()
+        };
+        fs::write(dir.path().join(LOCK_FILE), format_owner(&owner)).unwrap();
+
+        Lock::acquire(dir.path(), None).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+const LOGS_DIR: &str = ".logs";
+
+/// Number of run logs kept per migration unless configured otherwise
+pub const DEFAULT_LOG_RETENTION: usize = 10;
+
+/// Directory holding the run logs of a migration: `.logs/<id>` under the migrations directory.
+pub fn log_dir(migrations_dir: &Path, id: &str) -> PathBuf {
+    migrations_dir.join(LOGS_DIR).join(id)
+}
+
+/// Path for a new run log of a migration, named after the current time.
+pub fn new_log_path(migrations_dir: &Path, id: &str) -> PathBuf {
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+    log_dir(migrations_dir, id).join(format!("{}.log", timestamp))
+}
+
+/// List the run logs of a migration, oldest first.
+pub fn list_logs(migrations_dir: &Path, id: &str) -> Result<Vec<PathBuf>> {
+    let dir = log_dir(migrations_dir, id);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut logs: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read log directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+
+    // Timestamped names sort chronologically
+    logs.sort();
+    Ok(logs)
+}
+
+/// IDs of all migrations that have run logs.
+pub fn logged_ids(migrations_dir: &Path) -> Result<Vec<String>> {
+    let dir = migrations_dir.join(LOGS_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut ids: Vec<String> = fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read log directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(|s| s.to_string()))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
+
+/// Delete all but the newest `keep` run logs of a migration.
+/// Returns the number of logs deleted.
+pub fn prune_logs(migrations_dir: &Path, id: &str, keep: usize) -> Result<usize> {
+    let logs = list_logs(migrations_dir, id)?;
+    let excess = logs.len().saturating_sub(keep);
+
+    for path in &logs[..excess] {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to delete log file: {}", path.display()))?;
+    }
+
+    Ok(excess)
+}
+
+/// Log file for a single migration run, shared by the threads capturing its output.
+/// Every line is prefixed with a UTC timestamp and the stream it came from.
+#[derive(Clone)]
+pub struct RunLog {
+    file: Arc<Mutex<File>>,
+}
+
+impl RunLog {
+    /// Open the log file for appending, creating it and its parent directories if needed.
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create log directory: {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to create log file: {}", path.display()))?;
+
+        Ok(RunLog {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Append a line to the log.
+    pub fn write_line(&self, stream: &str, line: &[u8]) -> Result<()> {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+
+        write!(file, "{} [{}] ", timestamp, stream)?;
+        file.write_all(line.strip_suffix(b"\n").unwrap_or(line))?;
+        file.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    /// Copy a child's output stream to the terminal and into the log, line by line,
+    /// on a background thread that runs until the stream is closed.
+    pub fn tee<R, W>(&self, stream: &'static str, source: R, mut terminal: W) -> JoinHandle<()>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let log = self.clone();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(source);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        // Logging is best effort; the terminal copy matters most
+                        let _ = terminal.write_all(&line);
+                        let _ = terminal.flush();
+                        let _ = log.write_line(stream, &line);
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_keeps_newest_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = log_dir(dir.path(), "1f700-first");
+        fs::create_dir_all(&logs).unwrap();
+        for name in [
+            "20240101T000000.000Z.log",
+            "20240102T000000.000Z.log",
+            "20240103T000000.000Z.log",
+        ] {
+            fs::write(logs.join(name), "").unwrap();
+        }
+
+        assert_eq!(prune_logs(dir.path(), "1f700-first", 2).unwrap(), 1);
+
+        let remaining = list_logs(dir.path(), "1f700-first").unwrap();
+        assert_eq!(
+            remaining,
+            vec![
+                logs.join("20240102T000000.000Z.log"),
+                logs.join("20240103T000000.000Z.log"),
+            ]
+        );
+        assert_eq!(logged_ids(dir.path()).unwrap(), vec!["1f700-first"]);
+    }
+
+    #[test]
+    fn test_run_log_prefixes_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.log");
+        let log = RunLog::create(&path).unwrap();
+
+        log.write_line("stdout", b"hello\n").unwrap();
+        log.write_line("stderr", b"oops").unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" [stdout] hello"));
+        assert!(lines[1].ends_with(" [stderr] oops"));
+    }
+}
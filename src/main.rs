@@ -1,8 +1,22 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use migrate::commands;
+use migrate::commands::create::CreateOptions;
+use migrate::commands::down::DownOptions;
+use migrate::commands::test::TestOptions;
+use migrate::commands::up::UpOptions;
+use migrate::config::Config;
+use migrate::duration::parse_duration;
+use migrate::logs::DEFAULT_LOG_RETENTION;
+use migrate::render::parse_var;
+use migrate::selection::{Selection, TagFilter};
+use migrate::state::OutOfOrderPolicy;
+
+const DEFAULT_MIGRATIONS_DIR: &str = "migrations";
+const DEFAULT_TEMPLATE: &str = "bash";
 
 #[derive(Parser)]
 #[command(name = "migrate", version, about = "Generic file migration tool")]
@@ -11,32 +25,189 @@ struct Cli {
     #[arg(short = 'r', long, default_value = ".")]
     root: PathBuf,
 
-    /// Migrations directory
-    #[arg(short = 'm', long, default_value = "migrations")]
-    migrations: PathBuf,
+    /// Migrations directory [default: "migrations", or `migrations` in migrate.toml]
+    #[arg(short = 'm', long)]
+    migrations: Option<PathBuf>,
+
+    /// Kill migrations that run longer than this (e.g., "30s", "5m", "1h").
+    /// Overrides `timeout` in migrate.toml; a `Timeout:` header in the migration takes precedence
+    #[arg(long, global = true, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Number of run logs to keep per migration (0 disables output capture)
+    #[arg(long, global = true, default_value_t = DEFAULT_LOG_RETENTION)]
+    keep_logs: usize,
 
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Options selecting which pending migrations to apply
+#[derive(Args)]
+struct SelectionArgs {
+    /// Apply pending migrations up to and including this version (e.g., "1fb2g")
+    #[arg(long)]
+    to: Option<String>,
+
+    /// Apply only this pending migration (ID or version)
+    #[arg(long, conflicts_with_all = ["to", "from"])]
+    only: Option<String>,
+
+    /// Apply pending migrations starting at this migration (ID or version)
+    #[arg(long)]
+    from: Option<String>,
+
+    /// Allow --only to skip earlier pending migrations
+    #[arg(long, requires = "only")]
+    allow_skip: bool,
+
+    /// Apply only migrations with this tag (repeatable, or comma-separated)
+    #[arg(long = "tag", value_name = "TAG", value_delimiter = ',')]
+    tags: Vec<String>,
+
+    /// Leave out migrations with this tag (repeatable, or comma-separated)
+    #[arg(long = "exclude-tag", value_name = "TAG", value_delimiter = ',')]
+    exclude_tags: Vec<String>,
+}
+
+impl From<SelectionArgs> for Selection {
+    fn from(args: SelectionArgs) -> Self {
+        Selection {
+            to: args.to,
+            only: args.only,
+            from: args.from,
+            allow_skip: args.allow_skip,
+            tags: TagFilter {
+                include: args.tags,
+                exclude: args.exclude_tags,
+            },
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Show migration status
-    Status,
+    Status {
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+
+    /// Check that applied migration files haven't changed since they were applied
+    Verify,
 
     /// Apply pending migrations
     Up {
-        /// Preview without applying
+        /// Preview by running migrations in a scratch copy of the project
         #[arg(long)]
         dry_run: bool,
 
+        /// Show a unified diff of the changes a dry run would make
+        #[arg(long, requires = "dry_run")]
+        diff: bool,
+
         /// Create baseline at final version after applying (deletes old migration files)
-        #[arg(long)]
+        #[arg(long, conflicts_with_all = ["only", "from", "tags", "exclude_tags"])]
         baseline: bool,
 
         /// Keep migration files when using --baseline (don't delete)
         #[arg(long)]
         keep: bool,
+
+        /// Restore project files if a migration fails
+        #[arg(long)]
+        atomic: bool,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+
+        /// How to handle pending migrations older than the current version
+        #[arg(long, value_enum, default_value_t = OutOfOrderPolicy::Warn)]
+        out_of_order: OutOfOrderPolicy,
+
+        /// Wait up to this long for another run holding the migrations lock (e.g., "30s")
+        #[arg(long, value_parser = parse_duration)]
+        wait: Option<Duration>,
+    },
+
+    /// Revert applied migrations using their down scripts
+    Down {
+        /// Revert all migrations applied after this version (e.g., "1fb2g")
+        #[arg(long, conflicts_with = "steps")]
+        to: Option<String>,
+
+        /// Number of migrations to revert (default: 1)
+        #[arg(long)]
+        steps: Option<usize>,
+
+        /// Preview without reverting
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Wait up to this long for another run holding the migrations lock (e.g., "30s")
+        #[arg(long, value_parser = parse_duration)]
+        wait: Option<Duration>,
+    },
+
+    /// Undo applied migrations by restoring the files they changed
+    Undo {
+        /// Migration ID or version to undo (default: the most recent one)
+        id: Option<String>,
+
+        /// Number of most recent migrations to undo
+        #[arg(long, conflicts_with = "id")]
+        last: Option<usize>,
+
+        /// Overwrite files that were changed again after the migration ran
+        #[arg(long)]
+        force: bool,
+
+        /// Preview without undoing
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Wait up to this long for another run holding the migrations lock (e.g., "30s")
+        #[arg(long, value_parser = parse_duration)]
+        wait: Option<Duration>,
+    },
+
+    /// Show the files changed by an applied migration
+    Show {
+        /// Migration ID or version (e.g., "1fb2g-add-config" or "1fb2g")
+        id: String,
+
+        /// Print a unified diff instead of a summary
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// Test migrations against their fixtures (<id>.test/before and after)
+    Test {
+        /// Migration ID or version to test (default: all migrations with a fixture)
+        #[arg(conflicts_with = "idempotent")]
+        id: Option<String>,
+
+        /// Record the output as the fixture's expected after/ directory
+        #[arg(long, conflicts_with = "idempotent")]
+        update: bool,
+
+        /// Instead run each pending migration twice and report those whose second run fails or changes files
+        #[arg(long)]
+        idempotent: bool,
+
+        /// Show differences as a unified diff
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// Print the captured output of a migration run
+    Logs {
+        /// Migration ID or version (e.g., "1fb2g-add-config" or "1fb2g")
+        id: String,
+
+        /// Run number, oldest first (default: the latest run)
+        #[arg(long)]
+        run: Option<usize>,
     },
 
     /// Create a new migration
@@ -44,14 +215,22 @@ enum Commands {
         /// Migration name (e.g., "add-config")
         name: Option<String>,
 
-        /// Template to use (bash, ts, python, node, ruby)
-        #[arg(short = 't', long, default_value = "bash")]
-        template: String,
+        /// Template to use (see --list-templates) [default: "bash", or `template` in migrate.toml]
+        #[arg(short = 't', long)]
+        template: Option<String>,
 
         /// Migration description
         #[arg(short = 'd', long)]
         description: Option<String>,
 
+        /// Template variable (repeatable), e.g. --var table=users
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+
+        /// Create a directory migration (<id>/run.<ext>) that can bundle assets
+        #[arg(long)]
+        dir: bool,
+
         /// List available templates
         #[arg(long)]
         list_templates: bool,
@@ -73,51 +252,152 @@ enum Commands {
         /// Keep migration files (don't delete)
         #[arg(long)]
         keep: bool,
+
+        /// Wait up to this long for another run holding the migrations lock (e.g., "30s")
+        #[arg(long, value_parser = parse_duration)]
+        wait: Option<Duration>,
     },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let config = Config::load(&cli.root)?;
+
+    let migrations = cli
+        .migrations
+        .or(config.migrations)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_MIGRATIONS_DIR));
+    let timeout = cli.timeout.or(config.timeout);
 
     match cli.command {
-        Commands::Status => {
-            commands::status::run(&cli.root, &cli.migrations)?;
+        Commands::Status { selection } => {
+            commands::status::run(&cli.root, &migrations, &selection.into())?;
+        }
+        Commands::Verify => {
+            commands::verify::run(&cli.root, &migrations)?;
         }
         Commands::Up {
             dry_run,
+            diff,
             baseline,
             keep,
+            atomic,
+            wait,
+            selection,
+            out_of_order,
         } => {
-            commands::up::run(&cli.root, &cli.migrations, dry_run, baseline, keep)?;
+            let options = UpOptions {
+                dry_run,
+                diff,
+                create_baseline: baseline,
+                keep,
+                atomic,
+                timeout,
+                log_retention: cli.keep_logs,
+                wait,
+                selection: selection.into(),
+                out_of_order,
+                env: config.env,
+                interpreters: config.interpreters,
+            };
+            commands::up::run(&cli.root, &migrations, &options)?;
+        }
+        Commands::Down {
+            to,
+            steps,
+            dry_run,
+            wait,
+        } => {
+            let options = DownOptions {
+                to,
+                steps,
+                dry_run,
+                timeout,
+                log_retention: cli.keep_logs,
+                wait,
+                env: config.env,
+                interpreters: config.interpreters,
+            };
+            commands::down::run(&cli.root, &migrations, &options)?;
+        }
+        Commands::Undo {
+            id,
+            last,
+            force,
+            dry_run,
+            wait,
+        } => {
+            commands::undo::run(
+                &cli.root,
+                &migrations,
+                id.as_deref(),
+                last,
+                force,
+                dry_run,
+                wait,
+            )?;
+        }
+        Commands::Show { id, diff } => {
+            commands::show::run(&cli.root, &migrations, &id, diff)?;
+        }
+        Commands::Test {
+            id,
+            update,
+            idempotent,
+            diff,
+        } => {
+            commands::test::run(
+                &cli.root,
+                &migrations,
+                &TestOptions {
+                    id,
+                    update,
+                    idempotent,
+                    diff,
+                    timeout,
+                    env: config.env,
+                    interpreters: config.interpreters,
+                },
+            )?;
+        }
+        Commands::Logs { id, run } => {
+            commands::logs::run(&cli.root, &migrations, &id, run)?;
         }
         Commands::Create {
             name,
             template,
             description,
+            vars,
+            dir,
             list_templates,
         } => {
-            commands::create::run(
-                &cli.root,
-                &cli.migrations,
-                name.as_deref(),
-                &template,
-                description.as_deref(),
+            let options = CreateOptions {
+                name,
+                template: template
+                    .or(config.template)
+                    .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()),
+                description,
+                vars,
+                dir,
                 list_templates,
-            )?;
+            };
+            commands::create::run(&cli.root, &migrations, &options)?;
         }
         Commands::Baseline {
             version,
             summary,
             dry_run,
             keep,
+            wait,
         } => {
             commands::baseline::run(
                 &cli.root,
-                &cli.migrations,
+                &migrations,
                 &version,
                 summary.as_deref(),
                 dry_run,
                 keep,
+                wait,
             )?;
         }
     }
@@ -0,0 +1,152 @@
+use anyhow::{bail, Context, Result};
+use glob::{MatchOptions, Pattern};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::json;
+use crate::loader::{read_headers, split_list};
+use crate::logs::RunLog;
+use crate::tree::{resolve_within, walk, IgnoreRules, Tree};
+use crate::ExecutionContext;
+
+/// File name suffix of JSON patch migrations (e.g., `1fb2g-add-format-script.jsonpatch`)
+pub const EXTENSION: &str = ".jsonpatch";
+
+/// Whether a migration script is a JSON patch rather than a program.
+pub fn is_patch(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|f| f.to_str())
+        .is_some_and(|name| name.ends_with(EXTENSION))
+}
+
+/// Apply a JSON patch migration to the files named by its `Target:` header (paths or globs).
+/// A JSON array is applied as a JSON Patch (RFC 6902), an object as a JSON Merge Patch
+/// (RFC 7386). Every target is patched before any is written, so a failing operation, such
+/// as a `test` that doesn't match, leaves all of them untouched.
+pub fn apply(script: &Path, ctx: &ExecutionContext, log: Option<&RunLog>) -> Result<()> {
+    let headers = read_headers(script);
+    let Some(target) = headers.get("target") else {
+        bail!(
+            "{} has no Target: header naming the files to patch",
+            script.display()
+        );
+    };
+
+    let content = fs::read_to_string(script)
+        .with_context(|| format!("Failed to read migration: {}", script.display()))?;
+    let patch: Value = serde_json::from_str(strip_header(&content))
+        .with_context(|| format!("Invalid JSON in {}", script.display()))?;
+    if !patch.is_array() && !patch.is_object() {
+        bail!(
+            "{} must hold a JSON Patch array or a JSON Merge Patch object",
+            script.display()
+        );
+    }
+
+    let mut patched = Vec::new();
+    for path in find_targets(&split_list(target), ctx)? {
+        let relative = path
+            .strip_prefix(&ctx.project_root)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let (original, style) = json::read_file(&path)?;
+        let mut document = original.clone();
+        match &patch {
+            Value::Array(operations) => json::apply_patch(&mut document, operations)
+                .with_context(|| format!("Failed to patch {}", relative))?,
+            _ => json::merge_patch(&mut document, &patch),
+        }
+        // Unchanged files aren't rewritten, so reformatting alone never counts as a change
+        if document != original {
+            patched.push((relative, path, document, style));
+        }
+    }
+
+    for (relative, path, document, style) in patched {
+        let description = format!("patch {}", relative);
+        println!("  {}", description);
+        if let Some(log) = log {
+            log.write_line("op", description.as_bytes())?;
+        }
+        json::write_file(&path, &document, &style)?;
+    }
+    Ok(())
+}
+
+/// Resolve target patterns to files in the project. A plain path must name an existing file;
+/// a glob is matched against the project's files, skipping ignored ones, and must match at
+/// least one. Nothing outside the project or inside the migrations directory is a target.
+fn find_targets(patterns: &[String], ctx: &ExecutionContext) -> Result<BTreeSet<PathBuf>> {
+    let mut targets = BTreeSet::new();
+    let mut tree: Option<Tree> = None;
+    for pattern in patterns {
+        let resolved = resolve_within(&ctx.project_root, pattern)?;
+        if resolved.starts_with(&ctx.migrations_dir) {
+            bail!("Target '{}' is inside the migrations directory", pattern);
+        }
+
+        if !pattern.contains(['*', '?', '[']) {
+            if !resolved.is_file() {
+                bail!("Target '{}' not found", pattern);
+            }
+            targets.insert(resolved);
+            continue;
+        }
+
+        let relative = resolved.strip_prefix(&ctx.project_root)?;
+        let glob = Pattern::new(&relative.to_string_lossy())
+            .with_context(|| format!("Invalid target pattern '{}'", pattern))?;
+        let tree = match &mut tree {
+            Some(tree) => tree,
+            None => {
+                let rules = IgnoreRules::load(&ctx.project_root, &ctx.migrations_dir)?;
+                tree.insert(walk(&ctx.project_root, &rules)?)
+            }
+        };
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let matches: Vec<&PathBuf> = tree
+            .files
+            .iter()
+            .filter(|file| glob.matches_path_with(file, options))
+            .collect();
+        if matches.is_empty() {
+            bail!("Target '{}' matches no files", pattern);
+        }
+        targets.extend(matches.into_iter().map(|file| ctx.project_root.join(file)));
+    }
+    Ok(targets)
+}
+
+/// The JSON document after the comment lines (headers and notes) at the top of the file.
+fn strip_header(content: &str) -> &str {
+    let mut rest = content;
+    while !rest.is_empty() {
+        let end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let line = rest[..end].trim();
+        if !line.is_empty() && !line.starts_with("//") && !line.starts_with('#') {
+            break;
+        }
+        rest = &rest[end..];
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_header() {
+        let content =
+            "// Description: Add script\n// Target: package.json\n\n// Notes\n[\n  {}\n]\n";
+        assert_eq!(strip_header(content), "[\n  {}\n]\n");
+        assert_eq!(strip_header("{}"), "{}");
+        assert_eq!(strip_header("// only comments\n"), "");
+    }
+}
@@ -0,0 +1,381 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+/// Variable holding the current item inside `{{#each}}`
+const ITEM: &str = "this";
+
+/// A piece of a template between or inside `{{ }}` tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Text(&'a str),
+    Var { name: &'a str, line: usize },
+    If { name: &'a str, line: usize },
+    Else { line: usize },
+    EndIf { line: usize },
+    Each { name: &'a str, line: usize },
+    EndEach { line: usize },
+}
+
+impl Token<'_> {
+    /// Block tags on a line of their own don't leave a blank line behind
+    fn is_block(&self) -> bool {
+        !matches!(self, Token::Text(_) | Token::Var { .. })
+    }
+}
+
+#[derive(Debug)]
+enum Node<'a> {
+    Text(&'a str),
+    Var {
+        name: &'a str,
+        line: usize,
+    },
+    If {
+        name: &'a str,
+        then: Vec<Node<'a>>,
+        otherwise: Vec<Node<'a>>,
+    },
+    Each {
+        name: &'a str,
+        body: Vec<Node<'a>>,
+    },
+}
+
+/// Render a migration template.
+///
+/// - `{{NAME}}` inserts a variable; unknown variables are errors.
+/// - `{{#if NAME}}...{{else}}...{{/if}}` keeps the first part when the variable is set
+///   to anything but an empty string, `false`, `no` or `0`.
+/// - `{{#each NAME}}...{{/each}}` repeats its body for each item of a comma-separated
+///   variable, with the item in `{{this}}`.
+/// - `\{{` produces a literal `{{`.
+///
+/// Undefined variables count as false in `#if` and as empty lists in `#each`.
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut tokens = tokenize(template)?;
+    trim_standalone(&mut tokens);
+
+    let mut position = 0;
+    let (nodes, end) = parse(&tokens, &mut position)?;
+    if let Some(token) = end {
+        bail!(
+            "Unexpected {} on line {}",
+            describe(&token),
+            line_of(&token)
+        );
+    }
+
+    let mut out = String::with_capacity(template.len());
+    render_nodes(&nodes, vars, None, &mut out)?;
+    Ok(out)
+}
+
+/// Parse a `key=value` template variable from the command line.
+pub fn parse_var(arg: &str) -> Result<(String, String)> {
+    let Some((key, value)) = arg.split_once('=') else {
+        bail!("Invalid variable '{}': expected KEY=VALUE", arg);
+    };
+    let key = key.trim();
+    if !is_valid_name(key) {
+        bail!("Invalid variable name '{}'", key);
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    let mut line = 1;
+
+    while let Some(start) = rest.find("{{") {
+        // An escaped `\{{` is literal text
+        if rest[..start].ends_with('\\') {
+            tokens.push(Token::Text(&rest[..start - 1]));
+            tokens.push(Token::Text("{{"));
+            line += rest[..start].matches('\n').count();
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        tokens.push(Token::Text(&rest[..start]));
+        line += rest[..start].matches('\n').count();
+
+        let Some(length) = rest[start..].find("}}") else {
+            bail!("Unclosed '{{{{' on line {}", line);
+        };
+        let tag = rest[start + 2..start + length].trim();
+        tokens.push(parse_tag(tag, line)?);
+
+        line += tag.matches('\n').count();
+        rest = &rest[start + length + 2..];
+    }
+    tokens.push(Token::Text(rest));
+
+    tokens.retain(|t| *t != Token::Text(""));
+    Ok(tokens)
+}
+
+fn parse_tag(tag: &str, line: usize) -> Result<Token<'_>> {
+    let mut words = tag.split_whitespace();
+    let token = match (words.next(), words.next(), words.next()) {
+        (Some("#if"), Some(name), None) if is_valid_name(name) => Token::If { name, line },
+        (Some("#each"), Some(name), None) if is_valid_name(name) => Token::Each { name, line },
+        (Some("else"), None, None) => Token::Else { line },
+        (Some("/if"), None, None) => Token::EndIf { line },
+        (Some("/each"), None, None) => Token::EndEach { line },
+        (Some(name), None, None) if is_valid_name(name) => Token::Var { name, line },
+        _ => bail!("Invalid template tag '{{{{{}}}}}' on line {}", tag, line),
+    };
+    Ok(token)
+}
+
+/// Remove the indentation and line break around block tags that sit on a line of their own.
+fn trim_standalone(tokens: &mut [Token<'_>]) {
+    let last = tokens.len().saturating_sub(1);
+    let standalone: Vec<bool> = (0..tokens.len())
+        .map(|i| {
+            if !tokens[i].is_block() {
+                return false;
+            }
+            let before = match i.checked_sub(1).map(|j| tokens[j]) {
+                None => true,
+                Some(Token::Text(text)) => {
+                    let tail = text.rsplit('\n').next().unwrap_or_default();
+                    tail.trim().is_empty() && (text.contains('\n') || i == 1)
+                }
+                Some(_) => false,
+            };
+            let after = match tokens.get(i + 1) {
+                None => true,
+                Some(Token::Text(text)) => {
+                    let head = text.split('\n').next().unwrap_or_default();
+                    head.trim().is_empty() && (text.contains('\n') || i + 1 == last)
+                }
+                Some(_) => false,
+            };
+            before && after
+        })
+        .collect();
+
+    for (i, is_standalone) in standalone.into_iter().enumerate() {
+        if !is_standalone {
+            continue;
+        }
+        if let Some(Token::Text(text)) = i.checked_sub(1).map(|j| &mut tokens[j]) {
+            *text = text.rfind('\n').map_or("", |nl| &text[..=nl]);
+        }
+        if let Some(Token::Text(text)) = tokens.get_mut(i + 1) {
+            *text = text.find('\n').map_or("", |nl| &text[nl + 1..]);
+        }
+    }
+}
+
+/// Parse nodes until the end of the template or a tag that closes the enclosing block,
+/// which is returned alongside the nodes.
+fn parse<'a>(
+    tokens: &[Token<'a>],
+    position: &mut usize,
+) -> Result<(Vec<Node<'a>>, Option<Token<'a>>)> {
+    let mut nodes = Vec::new();
+
+    while let Some(&token) = tokens.get(*position) {
+        *position += 1;
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Var { name, line } => nodes.push(Node::Var { name, line }),
+            Token::If { name, line } => {
+                let (then, end) = parse(tokens, position)?;
+                let otherwise = match end {
+                    Some(Token::EndIf { .. }) => Vec::new(),
+                    Some(Token::Else { .. }) => match parse(tokens, position)? {
+                        (otherwise, Some(Token::EndIf { .. })) => otherwise,
+                        (_, end) => bail!(unclosed("{{#if}}", line, end)),
+                    },
+                    end => bail!(unclosed("{{#if}}", line, end)),
+                };
+                nodes.push(Node::If {
+                    name,
+                    then,
+                    otherwise,
+                });
+            }
+            Token::Each { name, line } => match parse(tokens, position)? {
+                (body, Some(Token::EndEach { .. })) => nodes.push(Node::Each { name, body }),
+                (_, end) => bail!(unclosed("{{#each}}", line, end)),
+            },
+            Token::Else { .. } | Token::EndIf { .. } | Token::EndEach { .. } => {
+                return Ok((nodes, Some(token)))
+            }
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+fn unclosed(block: &str, line: usize, end: Option<Token<'_>>) -> String {
+    match end {
+        Some(token) => format!(
+            "{} on line {} is closed by {} on line {}",
+            block,
+            line,
+            describe(&token),
+            line_of(&token)
+        ),
+        None => format!("{} on line {} is never closed", block, line),
+    }
+}
+
+fn describe(token: &Token<'_>) -> &'static str {
+    match token {
+        Token::Else { .. } => "{{else}}",
+        Token::EndIf { .. } => "{{/if}}",
+        Token::EndEach { .. } => "{{/each}}",
+        Token::If { .. } => "{{#if}}",
+        Token::Each { .. } => "{{#each}}",
+        Token::Var { .. } | Token::Text(_) => "text",
+    }
+}
+
+fn line_of(token: &Token<'_>) -> usize {
+    match *token {
+        Token::Var { line, .. }
+        | Token::If { line, .. }
+        | Token::Else { line }
+        | Token::EndIf { line }
+        | Token::Each { line, .. }
+        | Token::EndEach { line } => line,
+        Token::Text(_) => 0,
+    }
+}
+
+fn render_nodes(
+    nodes: &[Node<'_>],
+    vars: &BTreeMap<String, String>,
+    item: Option<&str>,
+    out: &mut String,
+) -> Result<()> {
+    let lookup = |name: &str| -> Option<&str> {
+        match (name, item) {
+            (ITEM, Some(item)) => Some(item),
+            _ => vars.get(name).map(|v| v.as_str()),
+        }
+    };
+
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var { name, line } => match lookup(name) {
+                Some(value) => out.push_str(value),
+                None => bail!(
+                    "Unknown template variable '{{{{{}}}}}' on line {}. Pass it with --var {}=<value>",
+                    name,
+                    line,
+                    name
+                ),
+            },
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                let branch = if lookup(name).is_some_and(is_truthy) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, vars, item, out)?;
+            }
+            Node::Each { name, body } => {
+                let items = lookup(name).unwrap_or_default();
+                for value in items.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+                    render_nodes(body, vars, Some(value), out)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_truthy(value: &str) -> bool {
+    !matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "" | "false" | "no" | "0"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_variables() {
+        let vars = vars(&[("ID", "1f700-init"), ("NAME", "init")]);
+        assert_eq!(
+            render("# {{ID}} ({{ NAME }})\n", &vars).unwrap(),
+            "# 1f700-init (init)\n"
+        );
+        assert_eq!(render("\\{{ID}}", &vars).unwrap(), "{{ID}}");
+
+        let err = render("a\n{{AUTHOR}}\n", &vars).unwrap_err().to_string();
+        assert!(err.contains("'{{AUTHOR}}' on line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_render_conditionals_and_loops() {
+        let template = "start\n{{#if down}}\ndown:\n  {{#each files}}\n  rm {{this}}\n  {{/each}}\n{{else}}\nno down\n{{/if}}\nend\n";
+
+        assert_eq!(
+            render(template, &vars(&[("down", "yes"), ("files", "a, b")])).unwrap(),
+            "start\ndown:\n  rm a\n  rm b\nend\n"
+        );
+        assert_eq!(
+            render(template, &vars(&[("down", "false")])).unwrap(),
+            "start\nno down\nend\n"
+        );
+        assert_eq!(
+            render(template, &vars(&[])).unwrap(),
+            "start\nno down\nend\n"
+        );
+        assert_eq!(
+            render("x{{#if a}}y{{/if}}z", &vars(&[("a", "1")])).unwrap(),
+            "xyz"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let empty = vars(&[]);
+        assert!(render("{{#if a}}never closed", &empty).is_err());
+        assert!(render("{{/each}}", &empty).is_err());
+        assert!(render("{{#if a}}{{/each}}", &empty).is_err());
+        assert!(render("{{not closed", &empty).is_err());
+        assert!(render("{{#unless a}}{{/unless}}", &empty).is_err());
+        assert!(render("{{this}}", &empty).is_err());
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(
+            parse_var("table=users=x").unwrap(),
+            ("table".to_string(), "users=x".to_string())
+        );
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("1bad=x").is_err());
+    }
+}
@@ -0,0 +1,480 @@
+use anyhow::{bail, Context, Result};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::duration::format_duration;
+use crate::json::{self, JsonStyle};
+use crate::logs::RunLog;
+use crate::tree::{copy_tree, resolve_within, walk, IgnoreRules};
+use crate::{ExecutionContext, Phase};
+
+/// File name suffix of embedded script migrations (e.g., `1fb2g-add-config.rhai`)
+pub const EXTENSION: &str = ".rhai";
+
+/// Maximum number of operations a script may run, so a runaway loop can't hang a run
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Maximum depth of nested function calls
+const MAX_CALL_LEVELS: usize = 64;
+
+/// Maximum length of a string, and number of items in an array or map
+const MAX_STRING_SIZE: usize = 64 * 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 1_000_000;
+
+/// Termination token of `satisfied()`
+const SATISFIED: &str = "satisfied";
+
+/// Termination token used when the migration's timeout runs out
+const TIMED_OUT: &str = "timed out";
+
+/// Whether a migration script is run by the embedded scripting engine.
+pub fn is_rhai(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|f| f.to_str())
+        .is_some_and(|name| name.ends_with(EXTENSION))
+}
+
+/// Run a Rhai migration in-process. The script can only touch files through the functions
+/// registered here, which are confined to the project root, and is stopped once it exceeds
+/// the operation limits or its timeout. Returns whether a check phase called `satisfied()`.
+pub fn run(script: &Path, ctx: &ExecutionContext, log: Option<&RunLog>) -> Result<bool> {
+    let content = fs::read_to_string(script)
+        .with_context(|| format!("Failed to read migration: {}", script.display()))?;
+
+    let engine = engine(ctx, log);
+    let ast = engine
+        .compile(&content)
+        .map_err(|err| anyhow::anyhow!("{}", err))
+        .with_context(|| format!("Invalid script {}", script.display()))?;
+
+    let mut scope = Scope::new();
+    scope.push_constant("migrate", context_map(ctx));
+    scope.push_constant(
+        "env",
+        ctx.env
+            .iter()
+            .map(|(key, value)| (key.into(), Dynamic::from(value.clone())))
+            .collect::<Map>(),
+    );
+
+    let Err(err) = engine.run_ast_with_scope(&mut scope, &ast) else {
+        return Ok(false);
+    };
+    match *err {
+        EvalAltResult::ErrorTerminated(ref token, _) if token.to_string() == SATISFIED => {
+            if ctx.phase != Phase::Check {
+                bail!("satisfied() can only be called in the check phase");
+            }
+            Ok(true)
+        }
+        EvalAltResult::ErrorTerminated(ref token, _) if token.to_string() == TIMED_OUT => bail!(
+            "Script timed out after {}",
+            format_duration(ctx.timeout.unwrap_or_default())
+        ),
+        EvalAltResult::ErrorTooManyOperations(position) => bail!(
+            "Script stopped after {} operations at {} (is there a runaway loop?)",
+            MAX_OPERATIONS,
+            position
+        ),
+        err => bail!("{}", err),
+    }
+}
+
+/// The values of the `MIGRATE_*` environment variables, for the `migrate` constant
+fn context_map(ctx: &ExecutionContext) -> Map {
+    let mut map = Map::new();
+    map.insert(
+        "project_root".into(),
+        ctx.project_root.display().to_string().into(),
+    );
+    map.insert(
+        "migrations_dir".into(),
+        ctx.migrations_dir.display().to_string().into(),
+    );
+    map.insert("id".into(), ctx.migration_id.clone().into());
+    map.insert("dry_run".into(), ctx.dry_run.into());
+    map.insert("direction".into(), ctx.direction.as_str().into());
+    map.insert("phase".into(), ctx.phase.as_str().into());
+    map
+}
+
+/// Build an engine with limits, no access to modules or `eval`, and the sandboxed API.
+fn engine(ctx: &ExecutionContext, log: Option<&RunLog>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .set_module_resolver(DummyModuleResolver::new())
+        .disable_symbol("eval");
+
+    // A timeout too long to represent can't run out
+    if let Some(deadline) = ctx
+        .timeout
+        .and_then(|timeout| Instant::now().checked_add(timeout))
+    {
+        engine.on_progress(move |_| (Instant::now() >= deadline).then(|| TIMED_OUT.into()));
+    }
+
+    let print_log = log.cloned();
+    engine.on_print(move |text| {
+        println!("{}", text);
+        if let Some(log) = &print_log {
+            let _ = log.write_line("stdout", text.as_bytes());
+        }
+    });
+    let debug_log = log.cloned();
+    engine.on_debug(move |text, _, position| {
+        eprintln!("{} {}", position, text);
+        if let Some(log) = &debug_log {
+            let _ = log.write_line("stderr", text.as_bytes());
+        }
+    });
+
+    register_api(&mut engine, ctx);
+    engine
+}
+
+/// Paths a script may use: reads anywhere in the project or the migration's asset
+/// directory, writes anywhere in the project but the migrations directory
+struct Sandbox {
+    project: PathBuf,
+    migrations: PathBuf,
+    assets: PathBuf,
+}
+
+impl Sandbox {
+    fn source(&self, path: &str) -> Result<PathBuf> {
+        resolve_within(&self.project, path)
+    }
+
+    fn target(&self, path: &str) -> Result<PathBuf> {
+        let resolved = resolve_within(&self.project, path)?;
+        if resolved.starts_with(&self.migrations) {
+            bail!("Path '{}' is inside the migrations directory", path);
+        }
+        Ok(resolved)
+    }
+
+    fn asset(&self, path: &str) -> Result<PathBuf> {
+        resolve_within(&self.assets, path)
+    }
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn script_error(err: anyhow::Error) -> Box<EvalAltResult> {
+    format!("{:#}", err).into()
+}
+
+fn register_api(engine: &mut Engine, ctx: &ExecutionContext) {
+    let sandbox = Rc::new(Sandbox {
+        project: ctx.project_root.clone(),
+        migrations: ctx.migrations_dir.clone(),
+        assets: ctx.migrations_dir.join(&ctx.migration_id),
+    });
+
+    engine.register_fn("satisfied", || -> ScriptResult<()> {
+        Err(EvalAltResult::ErrorTerminated(SATISFIED.into(), rhai::Position::NONE).into())
+    });
+
+    // Files
+    let s = sandbox.clone();
+    engine.register_fn("read", move |path: &str| -> ScriptResult<String> {
+        read(&s.source(path).map_err(script_error)?).map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn("read_asset", move |path: &str| -> ScriptResult<String> {
+        read(&s.asset(path).map_err(script_error)?).map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn(
+        "write",
+        move |path: &str, content: &str| -> ScriptResult<()> {
+            write(&s.target(path).map_err(script_error)?, content).map_err(script_error)
+        },
+    );
+    let s = sandbox.clone();
+    engine.register_fn("exists", move |path: &str| -> ScriptResult<bool> {
+        Ok(s.source(path).map_err(script_error)?.exists())
+    });
+    let s = sandbox.clone();
+    engine.register_fn("is_dir", move |path: &str| -> ScriptResult<bool> {
+        Ok(s.source(path).map_err(script_error)?.is_dir())
+    });
+    let s = sandbox.clone();
+    engine.register_fn("list", move |path: &str| -> ScriptResult<Array> {
+        list(&s.source(path).map_err(script_error)?).map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn("mkdir", move |path: &str| -> ScriptResult<()> {
+        let path = s.target(path).map_err(script_error)?;
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create directory: {}", path.display()))
+            .map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn("delete", move |path: &str| -> ScriptResult<()> {
+        delete(&s.target(path).map_err(script_error)?).map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn("copy", move |from: &str, to: &str| -> ScriptResult<()> {
+        let from = s.source(from).map_err(script_error)?;
+        copy(&from, &s.target(to).map_err(script_error)?).map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn(
+        "copy_asset",
+        move |asset: &str, to: &str| -> ScriptResult<()> {
+            let from = s.asset(asset).map_err(script_error)?;
+            copy(&from, &s.target(to).map_err(script_error)?).map_err(script_error)
+        },
+    );
+    let s = sandbox.clone();
+    engine.register_fn("rename", move |from: &str, to: &str| -> ScriptResult<()> {
+        let from = s.target(from).map_err(script_error)?;
+        let to = s.target(to).map_err(script_error)?;
+        create_parent(&to)
+            .and_then(|()| {
+                fs::rename(&from, &to)
+                    .with_context(|| format!("Failed to rename {}", from.display()))
+            })
+            .map_err(script_error)
+    });
+
+    // JSON
+    engine.register_fn("parse_json", |text: &str| -> ScriptResult<Dynamic> {
+        let value: Value = serde_json::from_str(text)
+            .context("Invalid JSON")
+            .map_err(script_error)?;
+        to_dynamic(value)
+    });
+    engine.register_fn("to_json", |value: Dynamic| -> ScriptResult<String> {
+        JsonStyle::default()
+            .format(&from_dynamic::<Value>(&value)?)
+            .map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn("read_json", move |path: &str| -> ScriptResult<Dynamic> {
+        let (value, _) =
+            json::read_file(&s.source(path).map_err(script_error)?).map_err(script_error)?;
+        to_dynamic(value)
+    });
+    let s = sandbox.clone();
+    engine.register_fn(
+        "write_json",
+        move |path: &str, value: Dynamic| -> ScriptResult<()> {
+            let path = s.target(path).map_err(script_error)?;
+            write_json(&path, from_dynamic(&value)?).map_err(script_error)
+        },
+    );
+
+    // TOML
+    engine.register_fn("parse_toml", |text: &str| -> ScriptResult<Dynamic> {
+        let value: toml::Table = toml::from_str(text)
+            .context("Invalid TOML")
+            .map_err(script_error)?;
+        to_dynamic(value)
+    });
+    engine.register_fn("to_toml", |value: Dynamic| -> ScriptResult<String> {
+        toml::to_string_pretty(&from_dynamic::<toml::Table>(&value)?)
+            .context("Failed to format TOML")
+            .map_err(script_error)
+    });
+    let s = sandbox.clone();
+    engine.register_fn("read_toml", move |path: &str| -> ScriptResult<Dynamic> {
+        let path = s.source(path).map_err(script_error)?;
+        let content = read(&path).map_err(script_error)?;
+        let value: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Invalid TOML in {}", path.display()))
+            .map_err(script_error)?;
+        to_dynamic(value)
+    });
+    let s = sandbox;
+    engine.register_fn(
+        "write_toml",
+        move |path: &str, value: Dynamic| -> ScriptResult<()> {
+            let path = s.target(path).map_err(script_error)?;
+            let content = toml::to_string_pretty(&from_dynamic::<toml::Table>(&value)?)
+                .context("Failed to format TOML")
+                .map_err(script_error)?;
+            write(&path, &content).map_err(script_error)
+        },
+    );
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Failed to read file: {}", path.display()))
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    create_parent(path)?;
+    fs::write(path, content).with_context(|| format!("Failed to write file: {}", path.display()))
+}
+
+/// Names of the entries in a directory, sorted
+fn list(path: &Path) -> Result<Array> {
+    let mut names: Vec<String> = fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory: {}", path.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    Ok(names.into_iter().map(Dynamic::from).collect())
+}
+
+/// Delete a file or directory, if it exists
+fn delete(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return Ok(()),
+    }
+    .with_context(|| format!("Failed to delete {}", path.display()))
+}
+
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    create_parent(to)?;
+    if from.is_dir() {
+        let tree = walk(from, &IgnoreRules::default())?;
+        fs::create_dir_all(to)?;
+        copy_tree(&tree, from, to)
+    } else {
+        fs::copy(from, to).with_context(|| format!("Failed to copy file: {}", from.display()))?;
+        Ok(())
+    }
+}
+
+/// Write a JSON file, keeping the indentation and key order of the file it replaces.
+fn write_json(path: &Path, mut value: Value) -> Result<()> {
+    let style = if path.exists() {
+        let (original, style) = json::read_file(path)?;
+        json::keep_order(&mut value, &original);
+        style
+    } else {
+        create_parent(path)?;
+        JsonStyle::default()
+    };
+    json::write_file(path, &value, &style)
+}
+
+fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Direction;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn run_script(project: &Path, phase: Phase, content: &str) -> Result<bool> {
+        let migrations_dir = project.join("migrations");
+        fs::create_dir_all(&migrations_dir).unwrap();
+        let script = migrations_dir.join("1fb2g-test.rhai");
+        fs::write(&script, content).unwrap();
+
+        let ctx = ExecutionContext {
+            project_root: project.to_path_buf(),
+            migrations_dir,
+            migration_id: "1fb2g-test".to_string(),
+            dry_run: false,
+            direction: Direction::Up,
+            phase,
+            // Too long to add to an instant, which must not overflow
+            timeout: Some(Duration::MAX),
+            log_path: None,
+            env: BTreeMap::from([("NODE_ENV".to_string(), "test".to_string())]),
+            interpreter: None,
+        };
+        run(&script, &ctx, None)
+    }
+
+    #[test]
+    fn test_file_and_json_api() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+        fs::write(
+            project.join("package.json"),
+            "{\n    \"name\": \"app\",\n    \"version\": \"1.0.0\"\n}\n",
+        )
+        .unwrap();
+
+        run_script(
+            project,
+            Phase::Run,
+            r#"
+let pkg = read_json("package.json");
+pkg.scripts = #{ format: "prettier --write ." };
+pkg.name = migrate.id;
+write_json("package.json", pkg);
+
+write("config/app.toml", to_toml(#{ mode: env.NODE_ENV }));
+let config = read_toml("config/app.toml");
+if config.mode != "test" { throw "unexpected mode"; }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(project.join("package.json")).unwrap(),
+            "{\n    \"name\": \"1fb2g-test\",\n    \"version\": \"1.0.0\",\n    \"scripts\": {\n        \"format\": \"prettier --write .\"\n    }\n}\n"
+        );
+        assert!(project.join("config/app.toml").exists());
+    }
+
+    #[test]
+    fn test_sandbox_and_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+
+        let err = run_script(project, Phase::Run, r#"write("../escape.txt", "x");"#).unwrap_err();
+        assert!(format!("{:#}", err).contains("leads outside"), "{:#}", err);
+
+        let err = run_script(project, Phase::Run, r#"delete("migrations");"#).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("migrations directory"),
+            "{:#}",
+            err
+        );
+
+        let err = run_script(project, Phase::Run, r#"import "other" as other;"#).unwrap_err();
+        assert!(format!("{:#}", err).contains("other"), "{:#}", err);
+
+        let err = run_script(project, Phase::Run, "loop { }").unwrap_err();
+        assert!(format!("{:#}", err).contains("runaway loop"), "{:#}", err);
+
+        let err = run_script(project, Phase::Run, r#"throw "not yet";"#).unwrap_err();
+        assert!(format!("{:#}", err).contains("not yet"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_check_phase() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+        let script = r#"
+if migrate.phase == "check" {
+    if exists(".editorconfig") { satisfied(); }
+    return;
+}
+write(".editorconfig", "root = true\n");
+"#;
+
+        assert!(!run_script(project, Phase::Check, script).unwrap());
+        assert!(!run_script(project, Phase::Run, script).unwrap());
+        assert!(run_script(project, Phase::Check, script).unwrap());
+        assert!(run_script(project, Phase::Run, "satisfied();").is_err());
+    }
+}
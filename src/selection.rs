@@ -0,0 +1,264 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::version::is_valid_version;
+use crate::Migration;
+
+/// Narrows down which pending migrations a run applies
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Apply pending migrations up to and including this version (or ID)
+    pub to: Option<String>,
+    /// Apply only this pending migration (ID or version)
+    pub only: Option<String>,
+    /// Apply pending migrations starting at this version (or ID)
+    pub from: Option<String>,
+    /// Allow `only` to skip earlier pending migrations
+    pub allow_skip: bool,
+    /// Apply only pending migrations whose `Tags:` header passes this filter
+    pub tags: TagFilter,
+}
+
+/// Filter on the tags from migrations' `Tags:` headers.
+/// Tags are compared ignoring case.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagFilter {
+    /// Only migrations with at least one of these tags pass (when non-empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Migrations with any of these tags are filtered out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    /// Whether the filter lets every migration through
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether a migration passes the filter
+    pub fn matches(&self, migration: &Migration) -> bool {
+        let tags = migration.tags();
+        let has = |wanted: &String| tags.iter().any(|t| t.eq_ignore_ascii_case(wanted));
+
+        (self.include.is_empty() || self.include.iter().any(has)) && !self.exclude.iter().any(has)
+    }
+}
+
+impl Selection {
+    /// Whether the selection leaves the pending migrations as they are
+    pub fn is_empty(&self) -> bool {
+        self.to.is_none() && self.only.is_none() && self.from.is_none() && self.tags.is_empty()
+    }
+
+    /// Select from the pending migrations, in the order they would be applied.
+    /// Fails if a selected migration requires a pending migration that isn't selected.
+    pub fn apply<'a>(&self, pending: &[&'a Migration]) -> Result<Vec<&'a Migration>> {
+        let selected = self.select(pending)?;
+
+        for migration in &selected {
+            for required in &migration.requires {
+                let is_pending = pending.iter().any(|m| m.id == *required);
+                let is_selected = selected.iter().any(|m| m.id == *required);
+                if is_pending && !is_selected {
+                    bail!(
+                        "Migration '{}' requires '{}', which is pending and not selected",
+                        migration.id,
+                        required
+                    );
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+
+    fn select<'a>(&self, pending: &[&'a Migration]) -> Result<Vec<&'a Migration>> {
+        if let Some(only) = &self.only {
+            let Some(index) = pending
+                .iter()
+                .position(|m| m.id == *only || m.version == *only)
+            else {
+                bail!("Migration '{}' is not pending", only);
+            };
+
+            if index > 0 && !self.allow_skip {
+                let skipped: Vec<&str> = pending[..index].iter().map(|m| m.id.as_str()).collect();
+                bail!(
+                    "Applying only '{}' would skip earlier pending migration(s): {}\nUse --allow-skip to apply it anyway.",
+                    pending[index].id,
+                    skipped.join(", ")
+                );
+            }
+
+            if !self.tags.matches(pending[index]) {
+                bail!(
+                    "Migration '{}' is excluded by the tag filter",
+                    pending[index].id
+                );
+            }
+
+            return Ok(vec![pending[index]]);
+        }
+
+        let to = self.to.as_deref().map(version_of).transpose()?;
+        let from = self.from.as_deref().map(version_of).transpose()?;
+
+        Ok(pending
+            .iter()
+            .filter(|m| to.is_none_or(|to| m.version.as_str() <= to))
+            .filter(|m| from.is_none_or(|from| m.version.as_str() >= from))
+            .filter(|m| self.tags.matches(m))
+            .copied()
+            .collect())
+    }
+}
+
+/// Version of a selector given as a version or a full migration ID.
+fn version_of(selector: &str) -> Result<&str> {
+    match selector.get(..5) {
+        Some(version)
+            if is_valid_version(version)
+                && (selector.len() == 5 || selector.as_bytes()[5] == b'-') =>
+        {
+            Ok(version)
+        }
+        _ => bail!(
+            "Invalid migration selector '{}': expected a version (e.g., \"1fb2g\") or ID",
+            selector
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrations() -> Vec<Migration> {
+        ["1f700-first", "1f710-second", "1f720-third"]
+            .iter()
+            .map(|id| Migration {
+                id: id.to_string(),
+                version: id[..5].to_string(),
+                file_path: format!("{}.sh", id).into(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn ids(selected: &[&Migration]) -> Vec<String> {
+        selected.iter().map(|m| m.id.clone()).collect()
+    }
+
+    #[test]
+    fn test_select_to_and_from() {
+        let available = migrations();
+        let pending: Vec<&Migration> = available.iter().collect();
+
+        let selection = Selection {
+            to: Some("1f710".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&selection.apply(&pending).unwrap()),
+            vec!["1f700-first", "1f710-second"]
+        );
+
+        let selection = Selection {
+            from: Some("1f710-second".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&selection.apply(&pending).unwrap()),
+            vec!["1f710-second", "1f720-third"]
+        );
+
+        let selection = Selection {
+            to: Some("nope".to_string()),
+            ..Default::default()
+        };
+        assert!(selection.apply(&pending).is_err());
+    }
+
+    #[test]
+    fn test_select_only_requires_allow_skip() {
+        let available = migrations();
+        let pending: Vec<&Migration> = available.iter().collect();
+
+        let selection = Selection {
+            only: Some("1f700".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&selection.apply(&pending).unwrap()),
+            vec!["1f700-first"]
+        );
+
+        let mut selection = Selection {
+            only: Some("1f720-third".to_string()),
+            ..Default::default()
+        };
+        let err = selection.apply(&pending).unwrap_err().to_string();
+        assert!(err.contains("1f700-first, 1f710-second"));
+
+        selection.allow_skip = true;
+        assert_eq!(
+            ids(&selection.apply(&pending).unwrap()),
+            vec!["1f720-third"]
+        );
+
+        selection.only = Some("1f799-missing".to_string());
+        assert!(selection.apply(&pending).is_err());
+    }
+
+    #[test]
+    fn test_select_by_tags() {
+        let mut available = migrations();
+        available[0]
+            .metadata
+            .insert("tags".to_string(), "ci, dev".to_string());
+        available[1]
+            .metadata
+            .insert("tags".to_string(), "CI".to_string());
+        let pending: Vec<&Migration> = available.iter().collect();
+
+        let include = Selection {
+            tags: TagFilter {
+                include: vec!["ci".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&include.apply(&pending).unwrap()),
+            vec!["1f700-first", "1f710-second"]
+        );
+
+        let exclude = Selection {
+            tags: TagFilter {
+                exclude: vec!["dev".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&exclude.apply(&pending).unwrap()),
+            vec!["1f710-second", "1f720-third"]
+        );
+    }
+
+    #[test]
+    fn test_select_rejects_unselected_requirements() {
+        let mut available = migrations();
+        available[0].requires = vec!["1f720-third".to_string()];
+        let pending: Vec<&Migration> = vec![&available[2], &available[0], &available[1]];
+
+        let selection = Selection {
+            to: Some("1f710".to_string()),
+            ..Default::default()
+        };
+        let err = selection.apply(&pending).unwrap_err().to_string();
+        assert!(err.contains("requires '1f720-third'"), "{}", err);
+    }
+}
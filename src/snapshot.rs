@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+use crate::tree::{copy_tree, diff_hashes, hash_files, walk, FileChange, IgnoreRules, Tree};
+
+/// A copy of the project tree taken before a migration runs, used to find
+/// what the migration changed and to put the tree back if it fails.
+pub struct Snapshot {
+    /// Project root the snapshot was taken from
+    root: PathBuf,
+    /// Rules used to decide which paths are part of the snapshot
+    rules: IgnoreRules,
+    /// Temporary directory holding the copied files
+    storage: TempDir,
+    /// Files and directories present when the snapshot was taken
+    tree: Tree,
+    /// Content hashes of the files present when the snapshot was taken
+    hashes: BTreeMap<PathBuf, String>,
+    /// Targets of the symlinks present when the snapshot was taken
+    links: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl Snapshot {
+    /// Copy all tracked files under the project root into temporary storage.
+    pub fn capture(root: &Path, rules: &IgnoreRules) -> Result<Self> {
+        let tree = walk(root, rules)?;
+        let storage = tempfile::tempdir().context("Failed to create snapshot directory")?;
+        copy_tree(&tree, root, storage.path())?;
+        let hashes = hash_files(storage.path(), &tree)?;
+        let links = read_links(root, &tree)?;
+
+        Ok(Snapshot {
+            root: root.to_path_buf(),
+            rules: rules.clone(),
+            storage,
+            tree,
+            hashes,
+            links,
+        })
+    }
+
+    /// Directory holding the saved copies, laid out like the project root.
+    pub fn storage_path(&self) -> &Path {
+        self.storage.path()
+    }
+
+    /// List files added, modified or deleted since the snapshot was taken.
+    pub fn changes(&self) -> Result<Vec<FileChange>> {
+        let current = walk(&self.root, &self.rules)?;
+        let current_hashes = hash_files(&self.root, &current)?;
+        Ok(diff_hashes(&self.hashes, &current_hashes))
+    }
+
+    /// Restore the project tree to the state it had when the snapshot was taken.
+    /// Files, directories and symlinks created since are removed, changed and deleted
+    /// files are put back, and symlinks are pointed at their original targets again.
+    pub fn restore(&self) -> Result<()> {
+        let current = walk(&self.root, &self.rules)?;
+        let current_links = read_links(&self.root, &current)?;
+
+        for file in current.files.difference(&self.tree.files) {
+            let path = self.root.join(file);
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove file: {}", path.display()))?;
+        }
+
+        for (link, target) in &current_links {
+            if self.links.get(link) == Some(target) {
+                continue;
+            }
+            let path = self.root.join(link);
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove symlink: {}", path.display()))?;
+        }
+
+        // Sorted order visits parents before children, so nested new directories
+        // are already gone by the time we reach them
+        for dir in current.dirs.difference(&self.tree.dirs) {
+            let path = self.root.join(dir);
+            if path.exists() {
+                fs::remove_dir_all(&path)
+                    .with_context(|| format!("Failed to remove directory: {}", path.display()))?;
+            }
+        }
+
+        for dir in &self.tree.dirs {
+            fs::create_dir_all(self.root.join(dir))?;
+        }
+
+        for file in &self.tree.files {
+            let saved = self.storage.path().join(file);
+            let target = self.root.join(file);
+            if current.files.contains(file) && files_equal(&saved, &target)? {
+                continue;
+            }
+            fs::copy(&saved, &target)
+                .with_context(|| format!("Failed to restore file: {}", target.display()))?;
+        }
+
+        for (link, target) in &self.links {
+            if current_links.get(link) == Some(target) {
+                continue;
+            }
+            create_link(target, &self.root.join(link))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A throwaway copy of the project and its migrations, for running migrations
+/// without touching the real tree.
+pub struct Scratch {
+    storage: TempDir,
+    project_root: PathBuf,
+    migrations_dir: PathBuf,
+}
+
+impl Scratch {
+    /// Copy the tracked project files and the whole migrations directory into a temporary directory.
+    /// The migrations directory keeps its position relative to the project root when it is inside it.
+    pub fn create(project_root: &Path, migrations_dir: &Path, rules: &IgnoreRules) -> Result<Self> {
+        let storage = tempfile::tempdir().context("Failed to create scratch directory")?;
+
+        let (scratch_root, scratch_migrations) = match migrations_dir.strip_prefix(project_root) {
+            Ok(relative) => {
+                let root = storage.path().to_path_buf();
+                let migrations = root.join(relative);
+                (root, migrations)
+            }
+            Err(_) => (
+                storage.path().join("project"),
+                storage.path().join("migrations"),
+            ),
+        };
+
+        fs::create_dir_all(&scratch_root)?;
+        copy_tree(&walk(project_root, rules)?, project_root, &scratch_root)?;
+
+        fs::create_dir_all(&scratch_migrations)?;
+        copy_tree(
+            &walk(migrations_dir, &IgnoreRules::default())?,
+            migrations_dir,
+            &scratch_migrations,
+        )?;
+
+        Ok(Scratch {
+            storage,
+            project_root: scratch_root,
+            migrations_dir: scratch_migrations,
+        })
+    }
+
+    /// Root of the copied project
+    pub fn project_root(&self) -> &Path {
+        &self.project_root
+    }
+
+    /// Copied migrations directory
+    pub fn migrations_dir(&self) -> &Path {
+        &self.migrations_dir
+    }
+
+    /// Temporary directory holding the whole copy
+    pub fn path(&self) -> &Path {
+        self.storage.path()
+    }
+}
+
+/// Read the target of every symlink in a tree, keyed by relative path.
+fn read_links(root: &Path, tree: &Tree) -> Result<BTreeMap<PathBuf, PathBuf>> {
+    tree.links
+        .iter()
+        .map(|link| {
+            let path = root.join(link);
+            let target = fs::read_link(&path)
+                .with_context(|| format!("Failed to read symlink: {}", path.display()))?;
+            Ok((link.clone(), target))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn create_link(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link)
+        .with_context(|| format!("Failed to restore symlink: {}", link.display()))
+}
+
+#[cfg(not(unix))]
+fn create_link(_target: &Path, link: &Path) -> Result<()> {
+    anyhow::bail!(
+        "Cannot restore symlink on this platform: {}",
+        link.display()
+    )
+}
+
+/// Compare two files byte for byte.
+fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(fs::read(a)? == fs::read(b)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_since_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("keep.txt"), "keep").unwrap();
+        fs::write(root.join("edit.txt"), "before").unwrap();
+
+        let rules = IgnoreRules::load(root, &root.join("migrations")).unwrap();
+        let snapshot = Snapshot::capture(root, &rules).unwrap();
+        fs::write(root.join("edit.txt"), "after").unwrap();
+
+        let changes = snapshot.changes().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, PathBuf::from("edit.txt"));
+        assert_eq!(
+            fs::read_to_string(snapshot.storage_path().join("edit.txt")).unwrap(),
+            "before"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_puts_back_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        symlink("a.txt", root.join("retarget")).unwrap();
+        symlink("a.txt", root.join("remove")).unwrap();
+        symlink("a.txt", root.join("replace")).unwrap();
+
+        let rules = IgnoreRules::load(root, &root.join("migrations")).unwrap();
+        let snapshot = Snapshot::capture(root, &rules).unwrap();
+
+        fs::remove_file(root.join("retarget")).unwrap();
+        symlink("b.txt", root.join("retarget")).unwrap();
+        fs::remove_file(root.join("remove")).unwrap();
+        fs::remove_file(root.join("replace")).unwrap();
+        fs::write(root.join("replace"), "file").unwrap();
+        fs::create_dir(root.join("new")).unwrap();
+        symlink("../a.txt", root.join("new/link")).unwrap();
+        symlink("b.txt", root.join("added")).unwrap();
+
+        snapshot.restore().unwrap();
+
+        for link in ["retarget", "remove", "replace"] {
+            assert_eq!(
+                fs::read_link(root.join(link)).unwrap(),
+                PathBuf::from("a.txt")
+            );
+        }
+        assert!(fs::symlink_metadata(root.join("added")).is_err());
+        assert!(!root.join("new").exists());
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_scratch_copies_project_and_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("migrations")).unwrap();
+        fs::write(root.join("README.md"), "readme").unwrap();
+        fs::write(root.join("migrations/00001-a.sh"), "#!/bin/sh").unwrap();
+
+        let rules = IgnoreRules::load(root, &root.join("migrations")).unwrap();
+        let scratch = Scratch::create(root, &root.join("migrations"), &rules).unwrap();
+
+        assert!(scratch.project_root().join("README.md").exists());
+        assert_eq!(
+            scratch.migrations_dir(),
+            scratch.project_root().join("migrations")
+        );
+        assert!(scratch.migrations_dir().join("00001-a.sh").exists());
+    }
+
+    #[test]
+    fn test_restore_reverts_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.ts"), "original").unwrap();
+        fs::write(root.join("README.md"), "readme").unwrap();
+
+        let rules = IgnoreRules::load(root, &root.join("migrations")).unwrap();
+        let snapshot = Snapshot::capture(root, &rules).unwrap();
+
+        fs::write(root.join("src/main.ts"), "changed").unwrap();
+        fs::remove_file(root.join("README.md")).unwrap();
+        fs::create_dir_all(root.join("new/nested")).unwrap();
+        fs::write(root.join("new/nested/file.txt"), "new").unwrap();
+        fs::write(root.join("added.txt"), "added").unwrap();
+
+        snapshot.restore().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join("src/main.ts")).unwrap(),
+            "original"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("README.md")).unwrap(),
+            "readme"
+        );
+        assert!(!root.join("new").exists());
+        assert!(!root.join("added.txt").exists());
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use chrono::{DateTime, Utc};
 use std::fs::{self, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
@@ -9,7 +9,12 @@ use crate::{AppliedMigration, Migration};
 
 const HISTORY_FILE: &str = ".history";
 
+/// First line of a history file in the current format.
+/// Each following line is one JSON-encoded `AppliedMigration`.
+const HISTORY_HEADER: &str = "# migrate history v2";
+
 /// Read the history file and return all applied migrations.
+/// Files in the original "id timestamp [checksum]" format are read as well.
 pub fn read_history(migrations_dir: &Path) -> Result<Vec<AppliedMigration>> {
     let history_path = migrations_dir.join(HISTORY_FILE);
 
@@ -23,47 +28,148 @@ pub fn read_history(migrations_dir: &Path) -> Result<Vec<AppliedMigration>> {
     let reader = BufReader::new(file);
     let mut applied = Vec::new();
 
-    for line in reader.lines() {
+    for (index, line) in reader.lines().enumerate() {
         let line = line.context("Failed to read line from history file")?;
         let line = line.trim();
 
-        if line.is_empty() {
+        if line.is_empty() || line.starts_with('#') {
             continue;
         }
 
-        // Format: "id timestamp" (space-separated)
-        let parts: Vec<&str> = line.splitn(2, ' ').collect();
-        if parts.len() != 2 {
-            continue;
-        }
+        let entry = parse_history_line(line).with_context(|| {
+            format!(
+                "Invalid entry on line {} of history file {}",
+                index + 1,
+                history_path.display()
+            )
+        })?;
+        applied.push(entry);
+    }
 
-        let id = parts[0].to_string();
-        let applied_at = DateTime::parse_from_rfc3339(parts[1])
-            .with_context(|| format!("Invalid timestamp in history file: {}", parts[1]))?
-            .with_timezone(&Utc);
+    Ok(applied)
+}
 
-        applied.push(AppliedMigration { id, applied_at });
+/// Parse a single history line in either the JSON or the original format.
+fn parse_history_line(line: &str) -> Result<AppliedMigration> {
+    if line.starts_with('{') {
+        return serde_json::from_str(line).context("Malformed JSON entry");
     }
 
-    Ok(applied)
+    // Original format: "id timestamp [checksum]" (space-separated)
+    let parts: Vec<&str> = line.splitn(3, ' ').collect();
+    if parts.len() < 2 {
+        bail!("Expected \"<id> <timestamp>\", got: {}", line);
+    }
+
+    let applied_at = DateTime::parse_from_rfc3339(parts[1])
+        .with_context(|| format!("Invalid timestamp in history file: {}", parts[1]))?
+        .with_timezone(&Utc);
+
+    Ok(AppliedMigration {
+        id: parts[0].to_string(),
+        applied_at,
+        checksum: parts.get(2).map(|c| c.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Whether an existing history file is already in the current format.
+fn is_current_format(history_path: &Path) -> Result<bool> {
+    let file = fs::File::open(history_path)
+        .with_context(|| format!("Failed to open history file: {}", history_path.display()))?;
+    let first_line = BufReader::new(file).lines().next().transpose()?;
+
+    Ok(match first_line {
+        Some(line) => line.trim() == HISTORY_HEADER,
+        // An empty file can be rewritten freely
+        None => false,
+    })
 }
 
 /// Append a migration record to the history file.
-pub fn append_history(migrations_dir: &Path, id: &str, applied_at: DateTime<Utc>) -> Result<()> {
+/// A history file in an older format is upgraded in place first.
+pub fn append_history(migrations_dir: &Path, migration: &AppliedMigration) -> Result<()> {
     let history_path = migrations_dir.join(HISTORY_FILE);
 
+    if !history_path.exists() || !is_current_format(&history_path)? {
+        let mut applied = read_history(migrations_dir)?;
+        applied.push(migration.clone());
+        return write_history(migrations_dir, &applied);
+    }
+
     let mut file = OpenOptions::new()
-        .create(true)
         .append(true)
         .open(&history_path)
         .with_context(|| format!("Failed to open history file: {}", history_path.display()))?;
 
-    writeln!(file, "{} {}", id, applied_at.to_rfc3339())
+    writeln!(file, "{}", format_history_line(migration)?)
         .context("Failed to write to history file")?;
 
     Ok(())
 }
 
+/// Remove migration records from the history file, keeping the order of the rest.
+pub fn remove_history(migrations_dir: &Path, ids: &[&str]) -> Result<()> {
+    let remaining: Vec<AppliedMigration> = read_history(migrations_dir)?
+        .into_iter()
+        .filter(|a| !ids.contains(&a.id.as_str()))
+        .collect();
+
+    write_history(migrations_dir, &remaining)
+}
+
+/// Find an applied migration by its full ID or just its version.
+pub fn find_applied<'a>(applied: &'a [AppliedMigration], id: &str) -> Option<&'a AppliedMigration> {
+    applied
+        .iter()
+        .find(|a| a.id == id || a.id.get(..5) == Some(id))
+}
+
+/// Rewrite the history file in the current format with the given records.
+fn write_history(migrations_dir: &Path, applied: &[AppliedMigration]) -> Result<()> {
+    let history_path = migrations_dir.join(HISTORY_FILE);
+
+    let mut content = String::from(HISTORY_HEADER);
+    content.push('\n');
+    for migration in applied {
+        content.push_str(&format_history_line(migration)?);
+        content.push('\n');
+    }
+
+    fs::write(&history_path, content)
+        .with_context(|| format!("Failed to write history file: {}", history_path.display()))?;
+
+    Ok(())
+}
+
+/// Format a history record as a single JSON line.
+fn format_history_line(migration: &AppliedMigration) -> Result<String> {
+    serde_json::to_string(migration).context("Failed to encode history entry")
+}
+
+/// Get applied migrations whose file has changed since they were applied.
+/// Migrations without a recorded checksum or without a file are not reported.
+pub fn get_modified<'a>(
+    available: &'a [Migration],
+    applied: &[AppliedMigration],
+) -> Result<Vec<&'a Migration>> {
+    let mut modified = Vec::new();
+
+    for entry in applied {
+        let Some(recorded) = &entry.checksum else {
+            continue;
+        };
+        let Some(migration) = available.iter().find(|m| m.id == entry.id) else {
+            continue;
+        };
+        if &migration.checksum()? != recorded {
+            modified.push(migration);
+        }
+    }
+
+    Ok(modified)
+}
+
 /// Get pending migrations (available but not yet applied).
 /// If a baseline is provided, skip migrations at or before the baseline version.
 pub fn get_pending<'a>(
@@ -92,7 +198,7 @@ pub fn get_pending<'a>(
         .collect()
 }
 
-/// Get the current version (version of the most recently applied migration).
+/// Get the current version: the version of the last applied migration in dependency order.
 /// Returns None if no migrations have been applied.
 pub fn get_current_version(
     available: &[Migration],
@@ -109,10 +215,51 @@ pub fn get_current_version(
         .map(|m| m.version.clone())
 }
 
+/// Get pending migrations that come before an applied one, typically added on a branch
+/// that was merged after newer migrations had already been applied.
+/// Positions are taken from `available`, which is in dependency order, so a migration
+/// placed after newer ones by its `Requires:` or `Before:` headers isn't reported.
+pub fn get_out_of_order<'a>(
+    available: &[Migration],
+    applied: &[AppliedMigration],
+    pending: &[&'a Migration],
+) -> Vec<&'a Migration> {
+    let applied_ids: std::collections::HashSet<&str> =
+        applied.iter().map(|a| a.id.as_str()).collect();
+    let Some(last_applied) = available
+        .iter()
+        .rposition(|m| applied_ids.contains(m.id.as_str()))
+    else {
+        return Vec::new();
+    };
+
+    let earlier: std::collections::HashSet<&str> = available[..last_applied]
+        .iter()
+        .map(|m| m.id.as_str())
+        .collect();
+    pending
+        .iter()
+        .filter(|m| earlier.contains(m.id.as_str()))
+        .copied()
+        .collect()
+}
+
+/// How to handle pending migrations that are older than the current version
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutOfOrderPolicy {
+    /// Refuse to apply them
+    Error,
+    /// Apply them, printing a warning
+    #[default]
+    Warn,
+    /// Apply them silently
+    Allow,
+}
+
 /// Get the target version (version of the latest available migration).
 /// Returns None if no migrations are available.
 pub fn get_target_version(available: &[Migration]) -> Option<String> {
-    available.last().map(|m| m.version.clone())
+    available.iter().map(|m| m.version.clone()).max()
 }
 
 #[cfg(test)]
@@ -126,22 +273,26 @@ mod tests {
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: "1f700-first.sh".into(),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: "1f710-second.sh".into(),
+                ..Default::default()
             },
             Migration {
                 id: "1f720-third".to_string(),
                 version: "1f720".to_string(),
                 file_path: "1f720-third.sh".into(),
+                ..Default::default()
             },
         ];
 
         let applied = vec![AppliedMigration {
             id: "1f700-first".to_string(),
             applied_at: Utc::now(),
+            ..Default::default()
         }];
 
         let pending = get_pending(&available, &applied, None);
@@ -157,16 +308,19 @@ mod tests {
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: "1f700-first.sh".into(),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: "1f710-second.sh".into(),
+                ..Default::default()
             },
             Migration {
                 id: "1f720-third".to_string(),
                 version: "1f720".to_string(),
                 file_path: "1f720-third.sh".into(),
+                ..Default::default()
             },
         ];
 
@@ -190,11 +344,13 @@ mod tests {
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: "1f700-first.sh".into(),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: "1f710-second.sh".into(),
+                ..Default::default()
             },
         ];
 
@@ -206,6 +362,7 @@ mod tests {
         let applied = vec![AppliedMigration {
             id: "1f700-first".to_string(),
             applied_at: Utc::now(),
+            ..Default::default()
         }];
         assert_eq!(
             get_current_version(&available, &applied),
@@ -217,10 +374,12 @@ mod tests {
             AppliedMigration {
                 id: "1f700-first".to_string(),
                 applied_at: Utc::now(),
+                ..Default::default()
             },
             AppliedMigration {
                 id: "1f710-second".to_string(),
                 applied_at: Utc::now(),
+                ..Default::default()
             },
         ];
         assert_eq!(
@@ -229,6 +388,160 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_out_of_order() {
+        let available = vec![
+            Migration {
+                id: "1fa00-branch".to_string(),
+                version: "1fa00".to_string(),
+                file_path: "1fa00-branch.sh".into(),
+                ..Default::default()
+            },
+            Migration {
+                id: "1fb00-main".to_string(),
+                version: "1fb00".to_string(),
+                file_path: "1fb00-main.sh".into(),
+                ..Default::default()
+            },
+            Migration {
+                id: "1fc00-next".to_string(),
+                version: "1fc00".to_string(),
+                file_path: "1fc00-next.sh".into(),
+                ..Default::default()
+            },
+        ];
+        let applied = vec![AppliedMigration {
+            id: "1fb00-main".to_string(),
+            applied_at: Utc::now(),
+            ..Default::default()
+        }];
+
+        let pending = get_pending(&available, &applied, None);
+        let out_of_order = get_out_of_order(&available, &applied, &pending);
+        assert_eq!(out_of_order.len(), 1);
+        assert_eq!(out_of_order[0].id, "1fa00-branch");
+
+        assert!(get_out_of_order(&available, &[], &pending).is_empty());
+
+        // A migration ordered after a newer one by its dependencies is in order
+        let ordered = vec![
+            available[1].clone(),
+            available[0].clone(),
+            available[2].clone(),
+        ];
+        let pending = get_pending(&ordered, &applied, None);
+        assert!(get_out_of_order(&ordered, &applied, &pending).is_empty());
+        assert_eq!(
+            get_current_version(&ordered, &applied),
+            Some("1fb00".to_string())
+        );
+    }
+
+    #[test]
+    fn test_remove_history() {
+        let dir = tempfile::tempdir().unwrap();
+        for id in ["1f700-first", "1f710-second", "1f720-third"] {
+            let record = AppliedMigration {
+                id: id.to_string(),
+                applied_at: Utc::now(),
+                checksum: Some(format!("{}-sum", id)),
+                ..Default::default()
+            };
+            append_history(dir.path(), &record).unwrap();
+        }
+
+        remove_history(dir.path(), &["1f710-second"]).unwrap();
+
+        let ids: Vec<String> = read_history(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|a| a.id)
+            .collect();
+        assert_eq!(ids, vec!["1f700-first", "1f720-third"]);
+    }
+
+    #[test]
+    fn test_read_history_with_and_without_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(HISTORY_FILE),
+            "1f700-first 2024-01-01T00:00:00+00:00\n1f710-second 2024-01-02T00:00:00+00:00 abc123\n",
+        )
+        .unwrap();
+
+        let applied = read_history(dir.path()).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[0].checksum, None);
+        assert_eq!(applied[1].checksum, Some("abc123".to_string()));
+    }
+
+    #[test]
+    fn test_append_upgrades_old_format() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(HISTORY_FILE),
+            "1f700-first 2024-01-01T00:00:00+00:00\n",
+        )
+        .unwrap();
+
+        let record = AppliedMigration {
+            id: "1f710-second".to_string(),
+            applied_at: Utc::now(),
+            duration_ms: Some(42),
+            exit_code: Some(0),
+            interpreter: Some("bash".to_string()),
+            ..Default::default()
+        };
+        append_history(dir.path(), &record).unwrap();
+
+        let content = fs::read_to_string(dir.path().join(HISTORY_FILE)).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], HISTORY_HEADER);
+        assert!(lines[1].starts_with("{\"id\":\"1f700-first\""));
+        assert!(lines[2].contains("\"duration_ms\":42"));
+
+        let applied = read_history(dir.path()).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[0].id, "1f700-first");
+        assert_eq!(applied[1], record);
+    }
+
+    #[test]
+    fn test_read_history_rejects_malformed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(HISTORY_FILE), "not-a-valid-entry\n").unwrap();
+
+        let err = read_history(dir.path()).unwrap_err();
+        assert!(format!("{:#}", err).contains("line 1"));
+    }
+
+    #[test]
+    fn test_get_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1f700-first.sh");
+        fs::write(&path, "echo original").unwrap();
+
+        let available = vec![Migration {
+            id: "1f700-first".to_string(),
+            version: "1f700".to_string(),
+            file_path: path.clone(),
+            ..Default::default()
+        }];
+        let applied = vec![AppliedMigration {
+            id: "1f700-first".to_string(),
+            applied_at: Utc::now(),
+            checksum: Some(available[0].checksum().unwrap()),
+            ..Default::default()
+        }];
+
+        assert!(get_modified(&available, &applied).unwrap().is_empty());
+
+        fs::write(&path, "echo edited").unwrap();
+        let modified = get_modified(&available, &applied).unwrap();
+        assert_eq!(modified.len(), 1);
+        assert_eq!(modified[0].id, "1f700-first");
+    }
+
     #[test]
     fn test_get_target_version() {
         let available: Vec<Migration> = vec![];
@@ -239,11 +552,13 @@ mod tests {
                 id: "1f700-first".to_string(),
                 version: "1f700".to_string(),
                 file_path: "1f700-first.sh".into(),
+                ..Default::default()
             },
             Migration {
                 id: "1f710-second".to_string(),
                 version: "1f710".to_string(),
                 file_path: "1f710-second.sh".into(),
+                ..Default::default()
             },
         ];
         assert_eq!(get_target_version(&available), Some("1f710".to_string()));
@@ -1,3 +1,10 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
 /// Template for creating new migration files
 pub struct Template {
     /// Template name (e.g., "bash", "ts")
@@ -34,14 +41,203 @@ pub static TEMPLATES: &[Template] = &[
         extension: ".rb",
         content: include_str!("../templates/ruby.rb"),
     },
+    Template {
+        name: "declarative",
+        extension: ".migrate.toml",
+        content: include_str!("../templates/declarative.migrate.toml"),
+    },
+    Template {
+        name: "jsonpatch",
+        extension: ".jsonpatch",
+        content: include_str!("../templates/patch.jsonpatch"),
+    },
+    Template {
+        name: "rhai",
+        extension: ".rhai",
+        content: include_str!("../templates/script.rhai"),
+    },
 ];
 
-/// Get a template by name
-pub fn get_template(name: &str) -> Option<&'static Template> {
-    TEMPLATES.iter().find(|t| t.name == name)
+/// Directory in the migrations directory holding project templates
+pub const PROJECT_TEMPLATES_DIR: &str = ".templates";
+
+/// Where a template comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    /// Compiled into the binary
+    BuiltIn,
+    /// A file in the user's template directory
+    User(PathBuf),
+    /// A file in the project's `.templates` directory
+    Project(PathBuf),
+}
+
+impl fmt::Display for TemplateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateSource::BuiltIn => write!(f, "built-in"),
+            TemplateSource::User(path) => write!(f, "user ({})", path.display()),
+            TemplateSource::Project(path) => write!(f, "project ({})", path.display()),
+        }
+    }
+}
+
+/// A template available to `migrate create`, built-in or loaded from a file
+#[derive(Debug, Clone)]
+pub struct ResolvedTemplate {
+    /// Template name (e.g., "bash", "house-style")
+    pub name: String,
+    /// File extension including the dot (e.g., ".sh")
+    pub extension: String,
+    /// Template content
+    pub content: String,
+    /// Where the template was loaded from
+    pub source: TemplateSource,
+}
+
+impl From<&Template> for ResolvedTemplate {
+    fn from(template: &Template) -> Self {
+        ResolvedTemplate {
+            name: template.name.to_string(),
+            extension: template.extension.to_string(),
+            content: template.content.to_string(),
+            source: TemplateSource::BuiltIn,
+        }
+    }
+}
+
+/// User-level template directory: `$XDG_CONFIG_HOME/migrate/templates`,
+/// or `~/.config/migrate/templates`.
+pub fn user_templates_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("migrate").join("templates"))
 }
 
-/// List all available template names
-pub fn list_templates() -> impl Iterator<Item = &'static str> {
-    TEMPLATES.iter().map(|t| t.name)
+/// All templates available to a project, sorted by name.
+/// User templates override built-ins with the same name, and project templates
+/// (`<migrations>/.templates/<name>.<ext>`) override both.
+pub fn available_templates(migrations_dir: &Path) -> Result<Vec<ResolvedTemplate>> {
+    let mut templates: BTreeMap<String, ResolvedTemplate> = TEMPLATES
+        .iter()
+        .map(|t| (t.name.to_string(), t.into()))
+        .collect();
+
+    if let Some(dir) = user_templates_dir() {
+        for template in load_templates(&dir, TemplateSource::User)? {
+            templates.insert(template.name.clone(), template);
+        }
+    }
+    for template in load_templates(
+        &migrations_dir.join(PROJECT_TEMPLATES_DIR),
+        TemplateSource::Project,
+    )? {
+        templates.insert(template.name.clone(), template);
+    }
+
+    Ok(templates.into_values().collect())
+}
+
+/// Load the `<name>.<ext>` template files in a directory. A missing directory has none.
+fn load_templates(
+    dir: &Path,
+    source: impl Fn(PathBuf) -> TemplateSource,
+) -> Result<Vec<ResolvedTemplate>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut templates = Vec::new();
+    for entry in fs::read_dir(dir)
+        .with_context(|| format!("Failed to read template directory: {}", dir.display()))?
+    {
+        let path = entry?.path();
+        let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        if filename.starts_with('.') || !path.is_file() {
+            continue;
+        }
+        let Some((name, extension)) = filename.split_once('.') else {
+            continue;
+        };
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read template: {}", path.display()))?;
+        templates.push(ResolvedTemplate {
+            name: name.to_string(),
+            extension: format!(".{}", extension),
+            content,
+            source: source(path.clone()),
+        });
+    }
+    Ok(templates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_templates_override_built_ins() {
+        // Keep the developer's own user templates out of the test
+        let home = tempfile::tempdir().unwrap();
+        env::set_var("HOME", home.path());
+        env::set_var("XDG_CONFIG_HOME", home.path());
+        let user_dir = home.path().join("migrate").join("templates");
+        fs::create_dir_all(&user_dir).unwrap();
+        fs::write(user_dir.join("python.py"), "# user\n").unwrap();
+        fs::write(user_dir.join("bash.sh"), "# user\n").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let templates_dir = dir.path().join(PROJECT_TEMPLATES_DIR);
+        fs::create_dir(&templates_dir).unwrap();
+        fs::write(
+            templates_dir.join("bash.sh"),
+            "#!/usr/bin/env bash\n# house\n",
+        )
+        .unwrap();
+        fs::write(templates_dir.join("config.migrate.toml"), "").unwrap();
+        fs::write(templates_dir.join(".hidden.sh"), "").unwrap();
+
+        let templates = available_templates(dir.path()).unwrap();
+
+        // Hidden files are skipped
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "bash",
+                "config",
+                "declarative",
+                "jsonpatch",
+                "node",
+                "python",
+                "rhai",
+                "ruby",
+                "ts"
+            ]
+        );
+
+        let bash = templates.iter().find(|t| t.name == "bash").unwrap();
+        assert_eq!(bash.content, "#!/usr/bin/env bash\n# house\n");
+        assert_eq!(
+            bash.source,
+            TemplateSource::Project(templates_dir.join("bash.sh"))
+        );
+
+        let python = templates.iter().find(|t| t.name == "python").unwrap();
+        assert_eq!(
+            python.source,
+            TemplateSource::User(user_dir.join("python.py"))
+        );
+
+        let config = templates.iter().find(|t| t.name == "config").unwrap();
+        assert_eq!(config.extension, ".migrate.toml");
+
+        let ts = templates.iter().find(|t| t.name == "ts").unwrap();
+        assert_eq!(ts.source, TemplateSource::BuiltIn);
+    }
 }
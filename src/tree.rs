@@ -0,0 +1,541 @@
+use anyhow::{bail, Context, Result};
+use glob::{MatchOptions, Pattern};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const IGNORE_FILE: &str = ".gitignore";
+
+/// A single pattern from an ignore file
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    pattern: Pattern,
+    /// Pattern starts with '!' and re-includes matching paths
+    negated: bool,
+    /// Pattern ends with '/' and only matches directories
+    dir_only: bool,
+    /// Pattern contains a '/' and is matched against the full relative path
+    anchored: bool,
+}
+
+/// Rules deciding which paths under the project root are tracked.
+/// `.git` and the migrations directory are always ignored; other rules come from
+/// the project's root `.gitignore` (nested ignore files are not read).
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    patterns: Vec<IgnorePattern>,
+    /// Paths relative to the project root that are always ignored
+    excluded: Vec<PathBuf>,
+}
+
+impl IgnoreRules {
+    /// Load ignore rules for a project.
+    pub fn load(project_root: &Path, migrations_dir: &Path) -> Result<Self> {
+        let mut rules = IgnoreRules {
+            patterns: Vec::new(),
+            excluded: vec![PathBuf::from(".git")],
+        };
+
+        if let Ok(relative) = migrations_dir.strip_prefix(project_root) {
+            rules.excluded.push(relative.to_path_buf());
+        }
+
+        let ignore_path = project_root.join(IGNORE_FILE);
+        if ignore_path.exists() {
+            let content = fs::read_to_string(&ignore_path).with_context(|| {
+                format!("Failed to read ignore file: {}", ignore_path.display())
+            })?;
+            rules.patterns = parse_ignore(&content);
+        }
+
+        Ok(rules)
+    }
+
+    /// Check whether a path relative to the project root is ignored.
+    pub fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.excluded.iter().any(|e| relative == e) {
+            return true;
+        }
+
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let name = relative
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let full = relative.to_string_lossy();
+
+        let mut ignored = false;
+        for p in &self.patterns {
+            if p.dir_only && !is_dir {
+                continue;
+            }
+            let candidate = if p.anchored { full.as_ref() } else { &name };
+            if p.pattern.matches_with(candidate, options) {
+                ignored = !p.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// Parse gitignore-style content into patterns. Invalid patterns are skipped.
+fn parse_ignore(content: &str) -> Vec<IgnorePattern> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.trim_start_matches('/');
+
+            Some(IgnorePattern {
+                pattern: Pattern::new(line).ok()?,
+                negated,
+                dir_only,
+                anchored,
+            })
+        })
+        .collect()
+}
+
+/// The files and directories under a root, as paths relative to that root.
+/// Symlinks are listed separately and never followed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub files: BTreeSet<PathBuf>,
+    pub dirs: BTreeSet<PathBuf>,
+    pub links: BTreeSet<PathBuf>,
+}
+
+/// Walk a directory tree, skipping ignored paths.
+pub fn walk(root: &Path, rules: &IgnoreRules) -> Result<Tree> {
+    let mut tree = Tree::default();
+    walk_dir(root, Path::new(""), rules, &mut tree)?;
+    Ok(tree)
+}
+
+fn walk_dir(root: &Path, relative: &Path, rules: &IgnoreRules, tree: &mut Tree) -> Result<()> {
+    let dir = root.join(relative);
+    let entries = fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+
+    for entry in entries {
+        let entry =
+            entry.with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        let file_type = entry.file_type()?;
+        let path = relative.join(entry.file_name());
+
+        if file_type.is_dir() {
+            if rules.is_ignored(&path, true) {
+                continue;
+            }
+            walk_dir(root, &path, rules, tree)?;
+            tree.dirs.insert(path);
+        } else if file_type.is_file() {
+            if rules.is_ignored(&path, false) {
+                continue;
+            }
+            tree.files.insert(path);
+        } else if file_type.is_symlink() {
+            if rules.is_ignored(&path, false) {
+                continue;
+            }
+            tree.links.insert(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the files and directories of a tree from one root to another, creating
+/// directories as needed. Symlinks are not copied.
+pub fn copy_tree(tree: &Tree, from: &Path, to: &Path) -> Result<()> {
+    for dir in &tree.dirs {
+        fs::create_dir_all(to.join(dir))
+            .with_context(|| format!("Failed to create directory: {}", to.join(dir).display()))?;
+    }
+    for file in &tree.files {
+        let target = to.join(file);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from.join(file), &target)
+            .with_context(|| format!("Failed to copy file: {}", from.join(file).display()))?;
+    }
+    Ok(())
+}
+
+/// Compute the SHA-256 of some bytes as a lowercase hex string.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compute the SHA-256 of a file's content.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let content =
+        fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok(hash_bytes(&content))
+}
+
+/// Permission bits of a file, on platforms that have them.
+pub fn file_mode(path: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path)
+            .ok()
+            .map(|m| m.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Set the permission bits of a file. Does nothing on platforms without them.
+pub fn set_file_mode(path: &Path, mode: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set permissions: {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+/// Hash every file of a tree, keyed by relative path.
+pub fn hash_files(root: &Path, tree: &Tree) -> Result<BTreeMap<PathBuf, String>> {
+    tree.files
+        .iter()
+        .map(|file| Ok((file.clone(), hash_file(&root.join(file))?)))
+        .collect()
+}
+
+/// Hash a whole directory: the relative path and content of every file in it.
+pub fn hash_dir(root: &Path) -> Result<String> {
+    let hashes = hash_files(root, &walk(root, &IgnoreRules::default())?)?;
+    let mut manifest = String::new();
+    for (path, hash) in &hashes {
+        manifest.push_str(&format!("{}\0{}\n", path.display(), hash));
+    }
+    Ok(hash_bytes(manifest.as_bytes()))
+}
+
+/// Resolve a relative path against a root, refusing paths that lead outside of it:
+/// absolute paths, `..` components that climb above the root, and symlinks that point
+/// elsewhere. The path itself doesn't need to exist.
+pub fn resolve_within(root: &Path, relative: &str) -> Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    bail!("Path '{}' leads outside of {}", relative, root.display());
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("Path '{}' must be relative", relative)
+            }
+        }
+    }
+    if resolved.as_os_str().is_empty() {
+        bail!("Path '{}' must name a file or directory", relative);
+    }
+
+    // The closest existing ancestor decides where the path really ends up
+    let path = root.join(&resolved);
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", root.display()))?;
+    let existing = path
+        .ancestors()
+        .find(|p| fs::symlink_metadata(p).is_ok())
+        .unwrap_or(&root);
+    let existing = existing
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", existing.display()))?;
+    if !existing.starts_with(&root) {
+        bail!(
+            "Path '{}' leads outside of {} through a symlink",
+            relative,
+            root.display()
+        );
+    }
+    Ok(path)
+}
+
+/// Kind of change made to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    /// Single-letter marker used in summaries and manifests
+    pub fn symbol(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+        }
+    }
+}
+
+/// A file whose content differs between two states of a tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    /// Path relative to the project root
+    pub path: PathBuf,
+    /// Content hash before the change (None if the file was added)
+    pub old_hash: Option<String>,
+    /// Content hash after the change (None if the file was deleted)
+    pub new_hash: Option<String>,
+    /// Permission bits before the change, when known
+    pub old_mode: Option<u32>,
+}
+
+impl FileChange {
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.old_hash, &self.new_hash) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Deleted,
+            _ => ChangeKind::Modified,
+        }
+    }
+}
+
+/// List the files that were added, modified or deleted between two sets of hashes.
+pub fn diff_hashes(
+    before: &BTreeMap<PathBuf, String>,
+    after: &BTreeMap<PathBuf, String>,
+) -> Vec<FileChange> {
+    let paths: BTreeSet<&PathBuf> = before.keys().chain(after.keys()).collect();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let old_hash = before.get(path);
+            let new_hash = after.get(path);
+            if old_hash == new_hash {
+                return None;
+            }
+            Some(FileChange {
+                path: path.clone(),
+                old_hash: old_hash.cloned(),
+                new_hash: new_hash.cloned(),
+                old_mode: None,
+            })
+        })
+        .collect()
+}
+
+/// List the files that differ between two copies of a project.
+pub fn compare_trees(
+    before_root: &Path,
+    after_root: &Path,
+    rules: &IgnoreRules,
+) -> Result<Vec<FileChange>> {
+    let before = hash_files(before_root, &walk(before_root, rules)?)?;
+    let after = hash_files(after_root, &walk(after_root, rules)?)?;
+    Ok(diff_hashes(&before, &after))
+}
+
+/// Render changes between two copies of a project as a file-level summary
+/// or, with `diff`, as a unified diff.
+pub fn render_changes(
+    changes: &[FileChange],
+    before_root: &Path,
+    after_root: &Path,
+    diff: bool,
+) -> Result<String> {
+    let mut out = String::new();
+
+    for change in changes {
+        if !diff {
+            out.push_str(&format!(
+                "  {}  {}\n",
+                change.kind().symbol(),
+                change.path.display()
+            ));
+            continue;
+        }
+
+        let read = |root: &Path, hash: &Option<String>| -> Result<Vec<u8>> {
+            match hash {
+                Some(_) => fs::read(root.join(&change.path))
+                    .with_context(|| format!("Failed to read file: {}", change.path.display())),
+                None => Ok(Vec::new()),
+            }
+        };
+        out.push_str(&unified_diff(
+            change,
+            &read(before_root, &change.old_hash)?,
+            &read(after_root, &change.new_hash)?,
+        ));
+    }
+
+    Ok(out)
+}
+
+/// Render a unified diff between the old and new content of a changed file.
+pub fn unified_diff(change: &FileChange, old: &[u8], new: &[u8]) -> String {
+    let path = change.path.display();
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return format!("Binary file {} differs\n", path);
+    };
+
+    let old_header = match change.old_hash {
+        Some(_) => format!("a/{}", path),
+        None => "/dev/null".to_string(),
+    };
+    let new_header = match change.new_hash {
+        Some(_) => format!("b/{}", path),
+        None => "/dev/null".to_string(),
+    };
+
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_header, &new_header)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(content: &str) -> IgnoreRules {
+        IgnoreRules {
+            patterns: parse_ignore(content),
+            excluded: vec![PathBuf::from(".git")],
+        }
+    }
+
+    #[test]
+    fn test_ignore_basename_patterns() {
+        let rules = rules("# comment\n*.log\nnode_modules/\n");
+        assert!(rules.is_ignored(Path::new("debug.log"), false));
+        assert!(rules.is_ignored(Path::new("src/debug.log"), false));
+        assert!(rules.is_ignored(Path::new("web/node_modules"), true));
+        assert!(!rules.is_ignored(Path::new("node_modules"), false));
+        assert!(!rules.is_ignored(Path::new("src/main.ts"), false));
+        assert!(rules.is_ignored(Path::new(".git"), true));
+    }
+
+    #[test]
+    fn test_ignore_anchored_and_negated_patterns() {
+        let rules = rules("/build\ndocs/*.md\n!docs/keep.md\n");
+        assert!(rules.is_ignored(Path::new("build"), true));
+        assert!(!rules.is_ignored(Path::new("src/build"), true));
+        assert!(rules.is_ignored(Path::new("docs/a.md"), false));
+        assert!(!rules.is_ignored(Path::new("docs/keep.md"), false));
+        assert!(!rules.is_ignored(Path::new("docs/sub/a.md"), false));
+    }
+
+    #[test]
+    fn test_walk_skips_ignored_and_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("migrations")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("target/debug/out"), "").unwrap();
+        fs::write(root.join("migrations/00001-a.sh"), "").unwrap();
+
+        let rules = IgnoreRules::load(root, &root.join("migrations")).unwrap();
+        let tree = walk(root, &rules).unwrap();
+
+        let files: Vec<_> = tree.files.iter().map(|p| p.to_str().unwrap()).collect();
+        assert_eq!(files, vec![".gitignore", "src/main.rs"]);
+        let dirs: Vec<_> = tree.dirs.iter().map(|p| p.to_str().unwrap()).collect();
+        assert_eq!(dirs, vec!["src"]);
+    }
+
+    #[test]
+    fn test_diff_hashes() {
+        let before: BTreeMap<PathBuf, String> = [
+            (PathBuf::from("same"), "1".to_string()),
+            (PathBuf::from("changed"), "2".to_string()),
+            (PathBuf::from("deleted"), "3".to_string()),
+        ]
+        .into();
+        let after: BTreeMap<PathBuf, String> = [
+            (PathBuf::from("same"), "1".to_string()),
+            (PathBuf::from("changed"), "4".to_string()),
+            (PathBuf::from("added"), "5".to_string()),
+        ]
+        .into();
+
+        let changes: Vec<_> = diff_hashes(&before, &after)
+            .into_iter()
+            .map(|c| (c.path.to_str().unwrap().to_string(), c.kind()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("added".to_string(), ChangeKind::Added),
+                ("changed".to_string(), ChangeKind::Modified),
+                ("deleted".to_string(), ChangeKind::Deleted),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_within() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+
+        assert_eq!(
+            resolve_within(root, "src/../new/file.txt").unwrap(),
+            root.join("new/file.txt")
+        );
+        assert!(resolve_within(root, "../outside").is_err());
+        assert!(resolve_within(root, "src/../../outside").is_err());
+        assert!(resolve_within(root, "/etc/passwd").is_err());
+        assert!(resolve_within(root, ".").is_err());
+
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir().unwrap();
+            std::os::unix::fs::symlink(outside.path(), root.join("link")).unwrap();
+            assert!(resolve_within(root, "link/file.txt").is_err());
+        }
+    }
+
+    #[test]
+    fn test_hash_bytes() {
+        assert_eq!(
+            hash_bytes(b"hello"),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+}
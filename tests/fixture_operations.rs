@@ -174,6 +174,100 @@ mv config.json.tmp config.json
     assert_eq!(json["settings"]["newFeature"], "enabled");
 }
 
+// =============================================================================
+// Test: Modify JSON file with a JSON Patch migration (no jq needed)
+// =============================================================================
+
+#[test]
+fn test_json_patch_migration() {
+    let temp_dir = setup_fixture();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-update-config.jsonpatch",
+        r#"// Description: Update config
+// Target: config.json
+
+[
+  { "op": "test", "path": "/version", "value": "1.0.0" },
+  { "op": "replace", "path": "/version", "value": "2.0.0" },
+  { "op": "add", "path": "/settings/newFeature", "value": "enabled" },
+  { "op": "add", "path": "/features/-", "value": "notifications" }
+]
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(
+        output.status.success(),
+        "Migration should succeed: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    // Key order and indentation are kept
+    let content = fs::read_to_string(temp_dir.path().join("config.json")).unwrap();
+    assert!(
+        content.starts_with("{\n  \"name\": \"sample-project\",\n  \"version\": \"2.0.0\",\n"),
+        "{}",
+        content
+    );
+    let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(json["settings"]["newFeature"], "enabled");
+    assert_eq!(json["features"][2], "notifications");
+}
+
+#[test]
+fn test_json_merge_patch_migration() {
+    let temp_dir = setup_fixture();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-update-settings.jsonpatch",
+        r#"// Target: *.json
+{ "settings": { "debug": true, "maxRetries": null } }
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(output.status.success(), "Migration should succeed");
+
+    let content = fs::read_to_string(temp_dir.path().join("config.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(json["settings"], serde_json::json!({ "debug": true }));
+    assert_eq!(json["version"], "1.0.0");
+}
+
+#[test]
+fn test_json_patch_failed_test_fails_migration() {
+    let temp_dir = setup_fixture();
+    let original = fs::read_to_string(temp_dir.path().join("config.json")).unwrap();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-guarded-update.jsonpatch",
+        r#"// Target: config.json
+[
+  { "op": "replace", "path": "/version", "value": "3.0.0" },
+  { "op": "test", "path": "/name", "value": "other-project" }
+]
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(!output.status.success(), "Migration should fail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Test failed"), "{}", stdout);
+
+    // Nothing is written and the migration isn't recorded
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("config.json")).unwrap(),
+        original
+    );
+    let history =
+        fs::read_to_string(temp_dir.path().join("migrations/.history")).unwrap_or_default();
+    assert!(!history.contains("00001-guarded-update"));
+}
+
 // =============================================================================
 // Test: Create new directory and files
 // =============================================================================
@@ -926,3 +1020,382 @@ VERIFY
     assert!(verify_content.contains("Read config: sample-project"));
     assert!(verify_content.contains("Features: auth, logging"));
 }
+
+// =============================================================================
+// Test: Atomic mode restores files when a migration fails
+// =============================================================================
+
+#[test]
+fn test_atomic_failure_restores_fixture() {
+    let temp_dir = setup_fixture();
+
+    let original_readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    let original_config = fs::read_to_string(temp_dir.path().join("config.json")).unwrap();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-half-written.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+cd "$MIGRATE_PROJECT_ROOT"
+
+echo "partial" > README.md
+rm config.json
+mkdir -p generated/nested
+echo "new" > generated/nested/file.txt
+exit 1
+"#,
+    );
+
+    let output = Command::new(get_binary_path())
+        .args([
+            "--root",
+            temp_dir.path().to_str().unwrap(),
+            "up",
+            "--atomic",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success(), "Migration should fail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("restored"), "stdout={}", stdout);
+
+    let readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    assert_eq!(readme, original_readme);
+    let config = fs::read_to_string(temp_dir.path().join("config.json")).unwrap();
+    assert_eq!(config, original_config);
+    assert!(!temp_dir.path().join("generated").exists());
+    assert!(!temp_dir.path().join("migrations/.history").exists());
+}
+
+#[test]
+fn test_failure_without_atomic_keeps_partial_changes() {
+    let temp_dir = setup_fixture();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-half-written.sh",
+        r#"#!/usr/bin/env bash
+cd "$MIGRATE_PROJECT_ROOT"
+echo "partial" > README.md
+exit 1
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(!output.status.success(), "Migration should fail");
+
+    let readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    assert_eq!(readme.trim(), "partial");
+}
+
+// =============================================================================
+// Test: Change journal records files touched by each migration
+// =============================================================================
+
+#[test]
+fn test_show_reports_changed_files() {
+    let temp_dir = setup_fixture();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-touch-files.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+cd "$MIGRATE_PROJECT_ROOT"
+
+sed 's/Hello, world!/Hello, journal!/' src/main.ts > src/main.ts.tmp
+mv src/main.ts.tmp src/main.ts
+rm data/users.csv
+echo "created" > NEW.md
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(output.status.success(), "Migration should succeed");
+
+    let output = Command::new(get_binary_path())
+        .args([
+            "--root",
+            temp_dir.path().to_str().unwrap(),
+            "show",
+            "00001-touch-files",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "show should succeed: {}", stdout);
+    assert!(stdout.contains("3 file(s) changed"), "stdout={}", stdout);
+    assert!(stdout.contains("A  NEW.md"));
+    assert!(stdout.contains("M  src/main.ts"));
+    assert!(stdout.contains("D  data/users.csv"));
+
+    // Versions resolve to the applied migration, and --diff prints a unified diff
+    let output = Command::new(get_binary_path())
+        .args([
+            "--root",
+            temp_dir.path().to_str().unwrap(),
+            "show",
+            "00001",
+            "--diff",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "show --diff should succeed");
+    assert!(stdout.contains("--- a/src/main.ts"), "stdout={}", stdout);
+    assert!(stdout.contains("+++ b/src/main.ts"));
+    assert!(stdout.contains("-  console.log(\"Hello, world!\");"));
+    assert!(stdout.contains("+  console.log(\"Hello, journal!\");"));
+    assert!(stdout.contains("--- /dev/null"));
+
+    // Arguments that aren't applied migrations are never used as journal paths
+    for id in ["00002", "../../src/main.ts"] {
+        let output = Command::new(get_binary_path())
+            .args(["--root", temp_dir.path().to_str().unwrap(), "show", id])
+            .output()
+            .expect("Failed to execute command");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "show {} should fail", id);
+        assert!(stderr.contains("No applied migration"), "stderr={}", stderr);
+    }
+}
+
+// =============================================================================
+// Test: Undo restores pre-images recorded in the change journal
+// =============================================================================
+
+fn run_undo(temp_dir: &Path, extra: &[&str]) -> std::process::Output {
+    Command::new(get_binary_path())
+        .args(["--root", temp_dir.to_str().unwrap(), "undo"])
+        .args(extra)
+        .output()
+        .expect("Failed to execute command")
+}
+
+#[test]
+fn test_undo_restores_files() {
+    let temp_dir = setup_fixture();
+    let original_readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    let original_csv = fs::read_to_string(temp_dir.path().join("data/users.csv")).unwrap();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-rewrite.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+cd "$MIGRATE_PROJECT_ROOT"
+echo "rewritten" > README.md
+rm data/users.csv
+echo "new" > NEW.md
+"#,
+    );
+    create_migration(
+        temp_dir.path(),
+        "00002-append.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+echo "again" >> "$MIGRATE_PROJECT_ROOT/README.md"
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(output.status.success(), "Migrations should succeed");
+
+    let output = run_undo(temp_dir.path(), &["--last", "2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Undo should succeed: stdout={}, stderr={}",
+        stdout,
+        stderr
+    );
+
+    let readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    assert_eq!(readme, original_readme);
+    let csv = fs::read_to_string(temp_dir.path().join("data/users.csv")).unwrap();
+    assert_eq!(csv, original_csv);
+    assert!(!temp_dir.path().join("NEW.md").exists());
+
+    let history = fs::read_to_string(temp_dir.path().join("migrations/.history")).unwrap();
+    assert!(!history.contains("00001-rewrite"), "history={}", history);
+    assert!(!history.contains("00002-append"), "history={}", history);
+}
+
+#[test]
+fn test_undo_refuses_when_file_changed_again() {
+    let temp_dir = setup_fixture();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-rewrite.sh",
+        r#"#!/usr/bin/env bash
+echo "rewritten" > "$MIGRATE_PROJECT_ROOT/README.md"
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(output.status.success(), "Migration should succeed");
+
+    fs::write(temp_dir.path().join("README.md"), "edited by hand\n").unwrap();
+
+    let output = run_undo(temp_dir.path(), &[]);
+    assert!(!output.status.success(), "Undo should refuse");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("README.md"), "stderr={}", stderr);
+    assert!(stderr.contains("--force"), "stderr={}", stderr);
+
+    let readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    assert_eq!(readme, "edited by hand\n");
+    let history = fs::read_to_string(temp_dir.path().join("migrations/.history")).unwrap();
+    assert!(history.contains("00001-rewrite"));
+
+    let output = run_undo(temp_dir.path(), &["--force"]);
+    assert!(output.status.success(), "Forced undo should succeed");
+    let readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    assert!(readme.contains("Sample Project"));
+}
+
+#[test]
+fn test_undo_restores_permissions_and_prunes_directories() {
+    let temp_dir = setup_fixture();
+
+    for name in ["build.sh", "deploy.sh"] {
+        let path = temp_dir.path().join(name);
+        fs::write(&path, "#!/usr/bin/env bash\necho original\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    create_migration(
+        temp_dir.path(),
+        "00001-replace-scripts.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+cd "$MIGRATE_PROJECT_ROOT"
+rm build.sh deploy.sh
+echo "echo replaced" > build.sh
+chmod 644 build.sh
+mkdir -p generated/nested
+echo "output" > generated/nested/out.txt
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(output.status.success(), "Migration should succeed");
+
+    let output = run_undo(temp_dir.path(), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Undo should succeed: {}", stderr);
+
+    for name in ["build.sh", "deploy.sh"] {
+        let path = temp_dir.path().join(name);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#!/usr/bin/env bash\necho original\n"
+        );
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o755, "{} should be executable again", name);
+    }
+    assert!(!temp_dir.path().join("generated").exists());
+}
+
+#[test]
+fn test_undo_rejects_unknown_ids() {
+    let temp_dir = setup_fixture();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-rewrite.sh",
+        r#"#!/usr/bin/env bash
+echo "rewritten" > "$MIGRATE_PROJECT_ROOT/README.md"
+"#,
+    );
+
+    let output = run_migrate(temp_dir.path());
+    assert!(output.status.success(), "Migration should succeed");
+
+    for id in ["00002", "00001-other", "../../00001-rewrite"] {
+        let output = run_undo(temp_dir.path(), &[id]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "undo {} should fail", id);
+        assert!(stderr.contains("has not been applied"), "stderr={}", stderr);
+    }
+
+    let readme = fs::read_to_string(temp_dir.path().join("README.md")).unwrap();
+    assert_eq!(readme.trim(), "rewritten");
+    let history = fs::read_to_string(temp_dir.path().join("migrations/.history")).unwrap();
+    assert!(history.contains("00001-rewrite"));
+}
+
+// =============================================================================
+// Test: Dry run executes in a scratch copy and reports what would change
+// =============================================================================
+
+#[test]
+fn test_dry_run_reports_changes_from_scratch_copy() {
+    let temp_dir = setup_fixture();
+    let original_main = fs::read_to_string(temp_dir.path().join("src/main.ts")).unwrap();
+
+    create_migration(
+        temp_dir.path(),
+        "00001-edit.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+cd "$MIGRATE_PROJECT_ROOT"
+sed 's/Hello, world!/Hello, preview!/' src/main.ts > src/main.ts.tmp
+mv src/main.ts.tmp src/main.ts
+echo "DRY_RUN=$MIGRATE_DRY_RUN" > dry-run.txt
+"#,
+    );
+    create_migration(
+        temp_dir.path(),
+        "00002-depends-on-first.sh",
+        r#"#!/usr/bin/env bash
+set -euo pipefail
+cd "$MIGRATE_PROJECT_ROOT"
+# Fails unless the previous migration ran in the same scratch copy
+grep -q "Hello, preview!" src/main.ts
+rm data/users.csv
+"#,
+    );
+
+    let output = Command::new(get_binary_path())
+        .args([
+            "--root",
+            temp_dir.path().to_str().unwrap(),
+            "up",
+            "--dry-run",
+            "--diff",
+        ])
+        .output()
+        .expect("Failed to execute command");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Dry run should succeed: stdout={}, stderr={}",
+        stdout,
+        stderr
+    );
+
+    assert!(
+        stdout.contains("3 file(s) would change"),
+        "stdout={}",
+        stdout
+    );
+    assert!(stdout.contains("+  console.log(\"Hello, preview!\");"));
+    assert!(stdout.contains("+++ b/dry-run.txt"));
+    assert!(stdout.contains("+DRY_RUN=true"));
+    assert!(stdout.contains("--- a/data/users.csv"));
+
+    // The real tree and history are untouched
+    let main = fs::read_to_string(temp_dir.path().join("src/main.ts")).unwrap();
+    assert_eq!(main, original_main);
+    assert!(!temp_dir.path().join("dry-run.txt").exists());
+    assert!(temp_dir.path().join("data/users.csv").exists());
+    assert!(!temp_dir.path().join("migrations/.history").exists());
+    assert!(!temp_dir.path().join("migrations/.journal").exists());
+}
@@ -141,6 +141,18 @@ fn test_create_detects_version_collision() {
         .output()
         .expect("Failed to execute command");
     assert!(output1.status.success());
+    let first = fs::read_dir(&migrations_dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .find(|name| name.ends_with("-first.sh"))
+        .expect("first migration should exist");
+
+    // A migration that fails to load doesn't switch off the collision check
+    fs::write(
+        migrations_dir.join("00001-broken.sh"),
+        "#!/usr/bin/env bash\n# Timeout: soon\n",
+    )
+    .unwrap();
 
     // Creating second migration immediately should either succeed (different 10-min slot)
     // or fail with version collision message
@@ -162,6 +174,13 @@ fn test_create_detects_version_collision() {
             "Should fail with version collision: {}",
             stderr
         );
+    } else {
+        let versions: Vec<String> = fs::read_dir(&migrations_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy()[..5].to_string())
+            .filter(|version| *version == first[..5])
+            .collect();
+        assert_eq!(versions.len(), 1, "Versions must not collide");
     }
 }
 
@@ -177,6 +196,7 @@ fn test_list_templates() {
             "dummy",
             "--list-templates",
         ])
+        .env("XDG_CONFIG_HOME", temp_dir.path())
         .output()
         .expect("Failed to execute command");
 
@@ -186,6 +206,14 @@ fn test_list_templates() {
     assert!(stdout.contains("python"));
     assert!(stdout.contains("node"));
     assert!(stdout.contains("ruby"));
+
+    // Columns line up, however long the extensions are
+    let columns: Vec<usize> = stdout
+        .lines()
+        .filter_map(|line| line.find("built-in"))
+        .collect();
+    assert!(columns.len() > 1, "{}", stdout);
+    assert!(columns.iter().all(|&c| c == columns[0]), "{}", stdout);
 }
 
 #[test]
@@ -396,3 +424,1548 @@ fn test_status_shows_applied_and_pending() {
     assert!(stdout.contains("Pending (1)"));
     assert!(stdout.contains("00002-second"));
 }
+
+#[test]
+fn test_down_reverts_last_migration() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    for (name, content) in [
+        (
+            "00001-first.sh",
+            "#!/usr/bin/env bash\ntouch \"$MIGRATE_PROJECT_ROOT/first.txt\"\n",
+        ),
+        (
+            "00002-second.sh",
+            "#!/usr/bin/env bash\ntouch \"$MIGRATE_PROJECT_ROOT/second.txt\"\n",
+        ),
+        (
+            "00002-second.down.sh",
+            "#!/usr/bin/env bash\necho \"$MIGRATE_DIRECTION\" > \"$MIGRATE_PROJECT_ROOT/direction.txt\"\nrm \"$MIGRATE_PROJECT_ROOT/second.txt\"\n",
+        ),
+    ] {
+        let path = migrations_dir.join(name);
+        fs::write(&path, content).unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(temp_dir.path().join("second.txt").exists());
+
+    // The down script itself must not be applied as a migration
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    assert!(!history.contains("00002-second.down"));
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "down"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Down should succeed: stdout={}, stderr={}",
+        stdout,
+        stderr
+    );
+
+    assert!(!temp_dir.path().join("second.txt").exists());
+    assert!(temp_dir.path().join("first.txt").exists());
+    let direction = fs::read_to_string(temp_dir.path().join("direction.txt")).unwrap();
+    assert_eq!(direction.trim(), "down");
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    assert!(history.contains("00001-first"));
+    assert!(!history.contains("00002-second"));
+}
+
+#[test]
+fn test_down_refuses_irreversible_migration() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let first = migrations_dir.join("00001-first.sh");
+    fs::write(&first, "#!/usr/bin/env bash\necho first").unwrap();
+    let mut perms = fs::metadata(&first).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&first, perms).unwrap();
+
+    fs::write(
+        migrations_dir.join(".history"),
+        "00001-first 2024-01-01T00:00:00+00:00\n",
+    )
+    .unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "down"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no down script"), "stderr={}", stderr);
+
+    // History is left untouched
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    assert!(history.contains("00001-first"));
+}
+
+#[test]
+fn test_status_shows_reversible() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    fs::write(
+        migrations_dir.join("00001-first.sh"),
+        "#!/usr/bin/env bash\n",
+    )
+    .unwrap();
+    fs::write(
+        migrations_dir.join("00001-first.down.sh"),
+        "#!/usr/bin/env bash\n",
+    )
+    .unwrap();
+    fs::write(
+        migrations_dir.join(".history"),
+        "00001-first 2024-01-01T00:00:00+00:00\n",
+    )
+    .unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "status"])
+        .output()
+        .expect("Failed to execute command");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied (1)"), "stdout={}", stdout);
+    assert!(stdout.contains("(reversible)"), "stdout={}", stdout);
+    assert!(!stdout.contains("Pending"), "stdout={}", stdout);
+}
+
+#[test]
+fn test_verify_detects_edited_migration() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let migration = migrations_dir.join("00001-first.sh");
+    fs::write(&migration, "#!/usr/bin/env bash\necho first\n").unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "verify"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success(), "Unchanged migration should verify");
+
+    // Edit the migration after it was applied
+    fs::write(&migration, "#!/usr/bin/env bash\necho edited\n").unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "verify"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(
+        !output.status.success(),
+        "Edited migration should fail verify"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("✗ 00001-first"), "stdout={}", stdout);
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("modified since applied"),
+        "stdout={}",
+        stdout
+    );
+}
+
+#[test]
+fn test_verify_accepts_history_without_checksums() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    fs::write(
+        migrations_dir.join("00001-first.sh"),
+        "#!/usr/bin/env bash\n",
+    )
+    .unwrap();
+    fs::write(
+        migrations_dir.join(".history"),
+        "00001-first 2024-01-01T00:00:00+00:00\n",
+    )
+    .unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "verify"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("no checksum recorded"), "stdout={}", stdout);
+}
+
+#[test]
+fn test_history_records_execution_metadata() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let migration = migrations_dir.join("00002-second.sh");
+    fs::write(&migration, "#!/usr/bin/env bash\necho second\n").unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    // Existing history in the original format
+    fs::write(
+        migrations_dir.join(".history"),
+        "00001-first 2024-01-01T00:00:00+00:00\n",
+    )
+    .unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    let lines: Vec<&str> = history.lines().collect();
+    assert_eq!(lines[0], "# migrate history v2");
+    assert_eq!(lines.len(), 3, "history={}", history);
+
+    let first: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+    assert_eq!(first["id"], "00001-first");
+
+    let second: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+    assert_eq!(second["id"], "00002-second");
+    assert_eq!(second["exit_code"], 0);
+    assert_eq!(second["interpreter"], "bash");
+    assert_eq!(second["migrate_version"], env!("CARGO_PKG_VERSION"));
+    assert!(second["duration_ms"].is_u64());
+    assert!(second["checksum"].is_string());
+}
+
+#[test]
+fn test_timeout_header_kills_process_group() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    // Leaks a background process, then hangs
+    let migration = migrations_dir.join("00001-hang.sh");
+    fs::write(
+        &migration,
+        "#!/usr/bin/env bash\n# Timeout: 1s\n\nsleep 300 &\necho $! > \"$MIGRATE_PROJECT_ROOT/background.pid\"\nsleep 300\n",
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    let started = std::time::Instant::now();
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "up"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!output.status.success());
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    assert!(stdout.contains("timed out"), "stdout={}", stdout);
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap_or_default();
+    assert!(!history.contains("00001-hang"));
+
+    // The leaked background process must be gone too (ignoring an unreaped zombie)
+    let pid = fs::read_to_string(temp_dir.path().join("background.pid")).unwrap();
+    let ps = Command::new("ps")
+        .args(["-o", "stat=", "-p", pid.trim()])
+        .output()
+        .expect("Failed to execute ps");
+    let stat = String::from_utf8_lossy(&ps.stdout);
+    assert!(
+        stat.trim().is_empty() || stat.trim().starts_with('Z'),
+        "background process still running: {}",
+        stat
+    );
+}
+
+#[test]
+fn test_background_processes_stopped_without_timeout() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    // Leaks a background process that holds the output pipes, then exits
+    let migration = migrations_dir.join("00001-leak.sh");
+    fs::write(
+        &migration,
+        "#!/usr/bin/env bash\nsleep 300 &\necho $! > \"$MIGRATE_PROJECT_ROOT/background.pid\"\n",
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", temp_dir.path().to_str().unwrap(), "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let pid = fs::read_to_string(temp_dir.path().join("background.pid")).unwrap();
+    let ps = Command::new("ps")
+        .args(["-o", "stat=", "-p", pid.trim()])
+        .output()
+        .expect("Failed to execute ps");
+    let stat = String::from_utf8_lossy(&ps.stdout);
+    assert!(
+        stat.trim().is_empty() || stat.trim().starts_with('Z'),
+        "background process still running: {}",
+        stat
+    );
+}
+
+#[test]
+fn test_global_timeout_flag() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let migration = migrations_dir.join("00001-slow.sh");
+    fs::write(&migration, "#!/usr/bin/env bash\nsleep 300\n").unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    let output = Command::new(get_binary_path())
+        .args([
+            "--root",
+            temp_dir.path().to_str().unwrap(),
+            "up",
+            "--timeout",
+            "500ms",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("timed out after 500ms"),
+        "stdout={}, stderr={}",
+        stdout,
+        stderr
+    );
+}
+
+#[test]
+fn test_huge_timeouts_do_not_overflow() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let migration = migrations_dir.join("00001-quick.sh");
+    fs::write(&migration, "#!/usr/bin/env bash\ntrue\n").unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+    let root = temp_dir.path().to_str().unwrap();
+
+    // Too large to add to an instant: the migration simply never times out
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up", "--timeout", "18446744073709551615"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Too large to represent at all: rejected when parsed
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up", "--timeout", "18446744073709551615h"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("duration too large"));
+}
+
+#[test]
+fn test_logs_capture_migration_output() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let migration = migrations_dir.join("00001-noisy.sh");
+    fs::write(
+        &migration,
+        "#!/usr/bin/env bash\necho \"to stdout\"\necho \"to stderr\" >&2\n[ -f \"$MIGRATE_PROJECT_ROOT/ok\" ] || exit 3\n",
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+
+    // First run fails, second succeeds
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("to stdout"), "stdout={}", stdout);
+    assert!(stderr.contains("to stderr"), "stderr={}", stderr);
+    assert!(stdout.contains("Log: "), "stdout={}", stdout);
+
+    fs::write(temp_dir.path().join("ok"), "").unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let log_dir = migrations_dir.join(".logs/00001-noisy");
+    assert_eq!(fs::read_dir(&log_dir).unwrap().count(), 2);
+
+    // Latest run by default
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "logs", "00001"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout={}", stdout);
+    assert!(stdout.contains("run 2 of 2"));
+    assert!(stdout.contains("[stdout] to stdout"));
+    assert!(stdout.contains("[stderr] to stderr"));
+    assert!(stdout.contains("exited with code 0"));
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "logs", "00001-noisy", "--run", "1"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("run 1 of 2"));
+    assert!(stdout.contains("failed with exit code 3"));
+
+    // Arguments that match no logged migration are errors, never paths
+    for id in ["00002", "../.logs/00001-noisy"] {
+        let output = Command::new(get_binary_path())
+            .args(["--root", root, "logs", id])
+            .output()
+            .expect("Failed to execute command");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "logs {} should fail", id);
+        assert!(stderr.contains("not found"), "stderr={}", stderr);
+    }
+
+    // Retention prunes the oldest runs
+    fs::remove_file(migrations_dir.join(".history")).unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "--keep-logs", "1", "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert_eq!(fs::read_dir(&log_dir).unwrap().count(), 1);
+}
+
+#[test]
+fn test_up_refuses_when_locked() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let migration = migrations_dir.join("00001-first.sh");
+    fs::write(&migration, "#!/usr/bin/env bash\necho first\n").unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+
+    // Held by a live process on another machine
+    let lock = migrations_dir.join(".lock");
+    fs::write(
+        &lock,
+        format!(
+            "pid: {}\nhost: elsewhere\nacquired: 2024-01-01T00:00:00+00:00\n",
+            std::process::id()
+        ),
+    )
+    .unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up", "--wait", "300ms"])
+        .output()
+        .expect("Failed to execute command");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("locked by pid") && stderr.contains("on elsewhere"),
+        "stderr={}",
+        stderr
+    );
+    assert!(!migrations_dir.join(".history").exists());
+
+    // A dry run doesn't need the lock
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up", "--dry-run"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    fs::remove_file(&lock).unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(!lock.exists(), "lock should be released after the run");
+}
+
+#[test]
+fn test_up_applies_selected_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    for name in ["00001-first", "00002-second", "00003-third"] {
+        let path = migrations_dir.join(format!("{}.sh", name));
+        fs::write(
+            &path,
+            format!(
+                "#!/usr/bin/env bash\ntouch \"$MIGRATE_PROJECT_ROOT/{}.txt\"\n",
+                name
+            ),
+        )
+        .unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["status", "--to", "00001"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("00002-second  (not selected)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Selected: 1 of 3"), "{}", stdout);
+
+    let output = run(&["up", "--to", "00001"]);
+    assert!(output.status.success());
+    assert!(temp_dir.path().join("00001-first.txt").exists());
+    assert!(!temp_dir.path().join("00002-second.txt").exists());
+
+    // --only refuses to skip an earlier pending migration without --allow-skip
+    let output = run(&["up", "--only", "00003-third"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("00002-second"), "{}", stderr);
+    assert!(!temp_dir.path().join("00003-third.txt").exists());
+
+    let output = run(&["up", "--only", "00003-third", "--allow-skip"]);
+    assert!(output.status.success());
+    assert!(temp_dir.path().join("00003-third.txt").exists());
+    assert!(!temp_dir.path().join("00002-second.txt").exists());
+}
+
+#[test]
+fn test_out_of_order_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let write_migration = |name: &str| {
+        let path = migrations_dir.join(name);
+        fs::write(&path, "#!/usr/bin/env bash\necho ok\n").unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    };
+
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    write_migration("1fb00-main.sh");
+    assert!(run(&["up"]).status.success());
+
+    // A branch merged an older migration after main was applied
+    write_migration("1fa00-branch.sh");
+
+    let output = run(&["status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("1fa00-branch  (out of order)"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("up to date"), "{}", stdout);
+
+    let output = run(&["up", "--out-of-order", "error"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("1fa00-branch"), "{}", stderr);
+
+    let output = run(&["up"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.contains("out of order"), "{}", stdout);
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    assert!(history.contains("1fa00-branch"));
+}
+
+#[test]
+fn test_migrations_run_after_their_dependencies() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let write_migration = |name: &str, header: &str| {
+        let path = migrations_dir.join(name);
+        fs::write(
+            &path,
+            format!(
+                "#!/usr/bin/env bash\n{}\n\necho \"$MIGRATE_ID\" >> \"$MIGRATE_PROJECT_ROOT/order.txt\"\n",
+                header
+            ),
+        )
+        .unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    };
+
+    write_migration("00001-first.sh", "# Requires: 00003-third");
+    write_migration("00002-second.sh", "# Before: 00003");
+    write_migration("00003-third.sh", "# Description: no dependencies");
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+
+    let order = fs::read_to_string(temp_dir.path().join("order.txt")).unwrap();
+    assert_eq!(order, "00002-second\n00003-third\n00001-first\n");
+
+    // A cycle is rejected before anything runs
+    write_migration("00004-fourth.sh", "# Requires: 00005");
+    write_migration("00005-fifth.sh", "# Requires: 00004-fourth");
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Dependency cycle"), "{}", stderr);
+
+    // So is a dependency that doesn't exist
+    write_migration("00005-fifth.sh", "# Requires: 00009-missing");
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("00009-missing"), "{}", stderr);
+}
+
+#[test]
+fn test_dependency_order_is_not_out_of_order() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    // The older migration runs after the newer one it requires, and fails the first time
+    for (name, body) in [
+        (
+            "00001-first.sh",
+            "# Requires: 00002-second\n[ -f \"$MIGRATE_PROJECT_ROOT/ready\" ] || exit 1\n",
+        ),
+        ("00002-second.sh", "true\n"),
+    ] {
+        let path = migrations_dir.join(name);
+        fs::write(&path, format!("#!/usr/bin/env bash\n{}", body)).unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["up"]);
+    assert!(!output.status.success());
+
+    let output = run(&["status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("00001-first"), "{}", stdout);
+    assert!(!stdout.contains("out of order"), "{}", stdout);
+
+    fs::write(temp_dir.path().join("ready"), "").unwrap();
+    let output = run(&["up", "--out-of-order", "error"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "stdout={}, stderr={}",
+        stdout,
+        stderr
+    );
+}
+
+#[test]
+fn test_descriptions_shown_and_recorded() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    for (name, content) in [
+        (
+            "00001-first.sh",
+            "#!/usr/bin/env bash\nset -euo pipefail\n# Description: Add config\n# Author: sam\n\necho first\n",
+        ),
+        (
+            "00002-second.sh",
+            "#!/usr/bin/env bash\n# Description: Set up CI\n\necho second\n",
+        ),
+    ] {
+        let path = migrations_dir.join(name);
+        fs::write(&path, content).unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("- 00001-first  Add config"), "{}", stdout);
+    assert!(stdout.contains("- 00002-second  Set up CI"), "{}", stdout);
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up", "--to", "00001"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("→ 00001-first  Add config"), "{}", stdout);
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    let entry: serde_json::Value = serde_json::from_str(history.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(entry["description"], "Add config");
+}
+
+#[test]
+fn test_tag_filters() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    for (name, tags) in [
+        ("00001-shared", ""),
+        ("00002-ci-only", "# Tags: ci"),
+        ("00003-laptop", "# Tags: dev, local"),
+    ] {
+        let path = migrations_dir.join(format!("{}.sh", name));
+        fs::write(
+            &path,
+            format!(
+                "#!/usr/bin/env bash\n{}\n\ntouch \"$MIGRATE_PROJECT_ROOT/{}.txt\"\n",
+                tags, name
+            ),
+        )
+        .unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["status", "--exclude-tag", "ci"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("00002-ci-only  (filtered by tag)"),
+        "{}",
+        stdout
+    );
+
+    let output = run(&["up", "--exclude-tag", "ci"]);
+    assert!(output.status.success());
+    assert!(temp_dir.path().join("00001-shared.txt").exists());
+    assert!(temp_dir.path().join("00003-laptop.txt").exists());
+    assert!(!temp_dir.path().join("00002-ci-only.txt").exists());
+
+    // The filtered migration is still pending
+    let output = run(&["status"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Pending (1)"), "{}", stdout);
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    let entry: serde_json::Value = serde_json::from_str(history.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(entry["tag_filter"]["exclude"][0], "ci");
+
+    let output = run(&["up", "--tag", "ci"]);
+    assert!(output.status.success());
+    assert!(temp_dir.path().join("00002-ci-only.txt").exists());
+}
+
+#[test]
+fn test_baseline_never_deletes_unselected_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    for (name, tags) in [("00001-untagged", ""), ("00002-ci", "# Tags: ci")] {
+        let path = migrations_dir.join(format!("{}.sh", name));
+        fs::write(&path, format!("#!/usr/bin/env bash\n{}\n", tags)).unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let root = temp_dir.path().to_str().unwrap();
+    for filter in ["--tag", "--exclude-tag"] {
+        let output = Command::new(get_binary_path())
+            .args(["--root", root, "up", filter, "ci", "--baseline"])
+            .output()
+            .expect("Failed to execute command");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            !output.status.success(),
+            "{} with --baseline should fail",
+            filter
+        );
+        assert!(stderr.contains("cannot be used with"), "stderr={}", stderr);
+    }
+
+    // The guard also holds for selections made outside the CLI
+    let options = migrate::commands::up::UpOptions {
+        create_baseline: true,
+        selection: migrate::selection::Selection {
+            tags: migrate::selection::TagFilter {
+                include: vec!["ci".to_string()],
+                exclude: Vec::new(),
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let err = migrate::commands::up::run(temp_dir.path(), &migrations_dir, &options).unwrap_err();
+    assert!(err.to_string().contains("00001-untagged"), "{}", err);
+
+    assert!(migrations_dir.join("00001-untagged.sh").exists());
+    assert!(migrations_dir.join("00002-ci.sh").exists());
+    assert!(!migrations_dir.join(".baseline").exists());
+    assert!(!migrations_dir.join(".history").exists());
+}
+
+#[test]
+fn test_check_phase_skips_satisfied_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    // Each migration creates its file unless the check finds it already there
+    for name in ["00001-first", "00002-second"] {
+        let path = migrations_dir.join(format!("{}.sh", name));
+        fs::write(
+            &path,
+            format!(
+                r#"#!/usr/bin/env bash
+# Check: true
+
+cd "$MIGRATE_PROJECT_ROOT"
+if [ "$MIGRATE_PHASE" = check ]; then
+  [ -f {name}.txt ] && exit "$MIGRATE_SATISFIED_EXIT_CODE"
+  exit 0
+fi
+echo ran >> {name}.txt
+"#
+            ),
+        )
+        .unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    // The first migration was already done by hand
+    fs::write(temp_dir.path().join("00001-first.txt"), "by hand\n").unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("already satisfied"), "{}", stdout);
+
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("00001-first.txt")).unwrap(),
+        "by hand\n"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("00002-second.txt")).unwrap(),
+        "ran\n"
+    );
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    let entries: Vec<serde_json::Value> = history
+        .lines()
+        .skip(1)
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries[0]["applied_by_check"], true);
+    assert!(entries[1].get("applied_by_check").is_none());
+
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("(applied by check)"), "{}", stdout);
+}
+
+#[test]
+fn test_idempotency_test_reports_appending_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    for (name, body) in [
+        (
+            "00001-write.sh",
+            "echo 'a' > \"$MIGRATE_PROJECT_ROOT/a.txt\"",
+        ),
+        (
+            "00002-append.sh",
+            "echo 'b' >> \"$MIGRATE_PROJECT_ROOT/b.txt\"",
+        ),
+    ] {
+        let path = migrations_dir.join(name);
+        fs::write(&path, format!("#!/usr/bin/env bash\n{}\n", body)).unwrap();
+        let mut perms = fs::metadata(&path).unwrap().permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&path, perms).unwrap();
+    }
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "test", "--idempotent"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stdout.contains("✓ idempotent"), "{}", stdout);
+    assert!(
+        stdout.contains("second run changed 1 file(s)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("M  b.txt"), "{}", stdout);
+    assert!(stderr.contains("00002-append"), "{}", stderr);
+
+    // Nothing touched the real project or history
+    assert!(!temp_dir.path().join("a.txt").exists());
+    assert!(!migrations_dir.join(".history").exists());
+}
+
+#[test]
+fn test_fixture_tests() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+
+    let path = migrations_dir.join("00001-add-setting.sh");
+    fs::write(
+        &path,
+        "#!/usr/bin/env bash\ncd \"$MIGRATE_PROJECT_ROOT\"\necho 'debug = true' >> config.toml\n",
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&path).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&path, perms).unwrap();
+
+    let fixture = migrations_dir.join("00001-add-setting.test");
+    fs::create_dir_all(fixture.join("before")).unwrap();
+    fs::create_dir_all(fixture.join("after")).unwrap();
+    fs::write(fixture.join("before/config.toml"), "name = \"app\"\n").unwrap();
+    fs::write(
+        fixture.join("after/config.toml"),
+        "name = \"app\"\ndebug = false\n",
+    )
+    .unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root, "test"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    // The fixture directory is not a migration
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Pending (1)"), "{}", stdout);
+
+    let output = run(&["--diff"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("-debug = false"), "{}", stdout);
+    assert!(stdout.contains("+debug = true"), "{}", stdout);
+
+    let output = run(&["00001", "--update"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(fixture.join("after/config.toml")).unwrap(),
+        "name = \"app\"\ndebug = true\n"
+    );
+
+    let output = run(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("✓ matches expected output"), "{}", stdout);
+
+    // The real project is never touched
+    assert!(!temp_dir.path().join("config.toml").exists());
+}
+
+#[test]
+fn test_config_file() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("db/migrations");
+    fs::create_dir_all(&migrations_dir).unwrap();
+
+    fs::write(
+        temp_dir.path().join("migrate.toml"),
+        r#"
+migrations = "db/migrations"
+
+[env]
+GREETING = "hello from config"
+
+[interpreters]
+sh = "bash -e"
+"#,
+    )
+    .unwrap();
+
+    // Not executable: the configured interpreter runs it
+    fs::write(
+        migrations_dir.join("00001-greet.sh"),
+        "echo \"$GREETING\" > \"$MIGRATE_PROJECT_ROOT/greeting.txt\"\n",
+    )
+    .unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "up"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("greeting.txt")).unwrap(),
+        "hello from config\n"
+    );
+
+    let history = fs::read_to_string(migrations_dir.join(".history")).unwrap();
+    let entry: serde_json::Value = serde_json::from_str(history.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(entry["interpreter"], "bash -e");
+
+    // The command line wins over the config file
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "--migrations", "elsewhere", "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("No migrations directory found"),
+        "{}",
+        stdout
+    );
+
+    fs::write(temp_dir.path().join("migrate.toml"), "migration = \"db\"\n").unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "status"])
+        .output()
+        .expect("Failed to execute command");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("unknown field `migration`"), "{}", stderr);
+}
+
+#[test]
+fn test_project_and_user_templates() {
+    let temp_dir = create_temp_dir();
+    let templates_dir = temp_dir.path().join("migrations/.templates");
+    fs::create_dir_all(&templates_dir).unwrap();
+    fs::write(
+        templates_dir.join("bash.sh"),
+        "#!/usr/bin/env bash\nset -euo pipefail\n# Description: {{DESCRIPTION}}\n# house style\n",
+    )
+    .unwrap();
+
+    let config_home = create_temp_dir();
+    let user_dir = config_home.path().join("migrate/templates");
+    fs::create_dir_all(&user_dir).unwrap();
+    fs::write(user_dir.join("mine.py"), "# Description: {{DESCRIPTION}}\n").unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .env("XDG_CONFIG_HOME", config_home.path())
+            .args(["--root", root, "create"])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["--list-templates"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = |name: &str| {
+        stdout
+            .lines()
+            .find(|l| l.trim_start().starts_with(&format!("{} ", name)))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(line("bash").contains("project ("), "{}", stdout);
+    assert!(line("mine").contains("user ("), "{}", stdout);
+    assert!(line("ts").contains("built-in"), "{}", stdout);
+
+    let output = run(&["setup", "-d", "Set up"]);
+    assert!(output.status.success());
+
+    let created: Vec<String> = fs::read_dir(temp_dir.path().join("migrations"))
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with("-setup.sh"))
+        .collect();
+    assert_eq!(created.len(), 1);
+    let content = fs::read_to_string(temp_dir.path().join("migrations").join(&created[0])).unwrap();
+    assert!(content.contains("# house style"));
+    assert!(content.contains("# Description: Set up"));
+}
+
+#[test]
+fn test_create_renders_template_variables() {
+    let temp_dir = create_temp_dir();
+    let templates_dir = temp_dir.path().join("migrations/.templates");
+    fs::create_dir_all(&templates_dir).unwrap();
+    fs::write(
+        templates_dir.join("table.sh"),
+        r#"#!/usr/bin/env bash
+# Description: {{DESCRIPTION}}
+# Created: {{DATE}}
+echo "{{ID}} ({{NAME}} at {{VERSION}}) creates {{table}}"
+{{#each columns}}
+echo "column {{this}}"
+{{/each}}
+{{#if down}}
+# down section
+{{/if}}
+"#,
+    )
+    .unwrap();
+    fs::write(templates_dir.join("broken.sh"), "echo {{TABLE}}\n").unwrap();
+
+    let root = temp_dir.path().to_str().unwrap();
+    let output = Command::new(get_binary_path())
+        .args(["--root", root, "create", "broken", "-t", "broken"])
+        .output()
+        .expect("Failed to execute command");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("'{{TABLE}}' on line 1"), "{}", stderr);
+
+    let output = Command::new(get_binary_path())
+        .args([
+            "--root",
+            root,
+            "create",
+            "add-users",
+            "-t",
+            "table",
+            "-d",
+            "Add users",
+            "--var",
+            "table=users",
+            "--var",
+            "columns=id, name",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let created: Vec<_> = fs::read_dir(temp_dir.path().join("migrations"))
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    assert_eq!(created.len(), 1, "the failed render created no file");
+
+    let id = created[0].file_stem().unwrap().to_str().unwrap();
+    let content = fs::read_to_string(&created[0]).unwrap();
+    assert!(content.contains("# Description: Add users\n"));
+    assert!(content.contains(&format!(
+        "echo \"{} (add-users at {}) creates users\"\necho \"column id\"\necho \"column name\"\n",
+        id,
+        &id[..5]
+    )));
+    assert!(!content.contains("down section"));
+    assert!(!content.contains("{{"));
+}
+
+#[test]
+fn test_directory_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let output = run(&["create", "add-config", "--dir"]);
+    assert!(output.status.success());
+
+    let migration_dir = fs::read_dir(&migrations_dir)
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.is_dir())
+        .expect("migration directory");
+    assert!(migration_dir.join("run.sh").exists());
+
+    // Bundle an asset and copy it into the project
+    fs::write(migration_dir.join("config.json"), "{\"debug\": true}\n").unwrap();
+    fs::write(
+        migration_dir.join("run.sh"),
+        "#!/usr/bin/env bash\nset -euo pipefail\n# Description: Add config\n\ncp \"$MIGRATE_MIGRATIONS_DIR/$MIGRATE_ID/config.json\" \"$MIGRATE_PROJECT_ROOT/config.json\"\n",
+    )
+    .unwrap();
+
+    let output = run(&["up"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("config.json")).unwrap(),
+        "{\"debug\": true}\n"
+    );
+
+    // Editing an asset counts as modifying the migration
+    fs::write(migration_dir.join("config.json"), "{}\n").unwrap();
+    let output = run(&["verify"]);
+    assert!(!output.status.success());
+
+    // Baselining removes the whole directory
+    let version = &migration_dir.file_name().unwrap().to_str().unwrap()[..5];
+    let output = run(&["baseline", version]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!migration_dir.exists());
+}
+
+#[test]
+fn test_declarative_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    fs::create_dir_all(migrations_dir.join("1fb2g-prettier")).unwrap();
+    fs::write(
+        migrations_dir.join("1fb2g-prettier/.prettierrc"),
+        "{\"semi\": false}\n",
+    )
+    .unwrap();
+    fs::write(
+        migrations_dir.join("1fb2g-prettier.migrate.toml"),
+        r#"# Description: Switch to prettier
+
+[[ops]]
+type = "copy"
+asset = ".prettierrc"
+to = ".prettierrc"
+
+[[ops]]
+type = "json_set"
+path = "package.json"
+pointer = "/scripts/format"
+value = "prettier --write ."
+
+[[ops]]
+type = "delete"
+path = ".eslintrc"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("package.json"),
+        "{\n  \"name\": \"app\"\n}\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join(".eslintrc"), "{}\n").unwrap();
+
+    // A dry run previews the changes without making them
+    let output = run(&["up", "--dry-run"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("copy 1fb2g-prettier/.prettierrc -> .prettierrc"));
+    assert!(stdout.contains("3 file(s) would change"), "{}", stdout);
+    assert!(temp_dir.path().join(".eslintrc").exists());
+
+    let output = run(&["up"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join(".prettierrc")).unwrap(),
+        "{\"semi\": false}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("package.json")).unwrap(),
+        "{\n  \"name\": \"app\",\n  \"scripts\": {\n    \"format\": \"prettier --write .\"\n  }\n}\n"
+    );
+    assert!(!temp_dir.path().join(".eslintrc").exists());
+
+    // Paths outside the project are refused before anything runs
+    fs::write(
+        migrations_dir.join("1fb2h-escape.migrate.toml"),
+        "[[ops]]\ntype = \"write\"\npath = \"ok.txt\"\ncontent = \"\"\n\n[[ops]]\ntype = \"delete\"\npath = \"../outside\"\n",
+    )
+    .unwrap();
+    let output = run(&["up"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("leads outside"), "{}", stdout);
+    assert!(!temp_dir.path().join("ok.txt").exists());
+}
+
+#[test]
+fn test_rhai_migrations() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir(&migrations_dir).unwrap();
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    fs::write(
+        temp_dir.path().join("package.json"),
+        "{\n  \"name\": \"app\"\n}\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join(".editorconfig"), "root = true\n").unwrap();
+    fs::write(
+        migrations_dir.join("1fb2g-add-script.rhai"),
+        r#"// Description: Add a format script
+
+let pkg = read_json("package.json");
+pkg.scripts = #{ format: "prettier --write ." };
+write_json("package.json", pkg);
+print(`updated ${migrate.id}`);
+"#,
+    )
+    .unwrap();
+    fs::write(
+        migrations_dir.join("1fb2h-add-editorconfig.rhai"),
+        r#"// Check: true
+
+if migrate.phase == "check" {
+    if exists(".editorconfig") { satisfied(); }
+    return;
+}
+write(".editorconfig", "root = true\n");
+"#,
+    )
+    .unwrap();
+
+    let output = run(&["up"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("updated 1fb2g-add-script"));
+    assert!(stdout.contains("already satisfied"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("package.json")).unwrap(),
+        "{\n  \"name\": \"app\",\n  \"scripts\": {\n    \"format\": \"prettier --write .\"\n  }\n}\n"
+    );
+
+    // Runaway loops and escapes from the project fail the migration instead of hanging it
+    fs::write(migrations_dir.join("1fb2i-spin.rhai"), "loop { }\n").unwrap();
+    let output = run(&["up"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("runaway loop"));
+
+    fs::write(
+        migrations_dir.join("1fb2i-spin.rhai"),
+        "write(\"../outside.txt\", \"x\");\n",
+    )
+    .unwrap();
+    let output = run(&["up"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("leads outside"));
+    assert!(!temp_dir
+        .path()
+        .parent()
+        .unwrap()
+        .join("outside.txt")
+        .exists());
+}
+
+#[test]
+fn test_baseline_deletes_asset_directories() {
+    let temp_dir = create_temp_dir();
+    let migrations_dir = temp_dir.path().join("migrations");
+    fs::create_dir_all(migrations_dir.join("00001-foo")).unwrap();
+    let root = temp_dir.path().to_str().unwrap();
+    let run = |args: &[&str]| {
+        Command::new(get_binary_path())
+            .args(["--root", root])
+            .args(args)
+            .output()
+            .expect("Failed to execute command")
+    };
+
+    let migration = migrations_dir.join("00001-foo.sh");
+    fs::write(
+        &migration,
+        "#!/usr/bin/env bash\nset -euo pipefail\ncp \"$MIGRATE_MIGRATIONS_DIR/$MIGRATE_ID/config.json\" \"$MIGRATE_PROJECT_ROOT/\"\n",
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&migration).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&migration, perms).unwrap();
+    fs::write(migrations_dir.join("00001-foo/config.json"), "{}\n").unwrap();
+
+    assert!(run(&["up"]).status.success());
+    let output = run(&["baseline", "00001"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!migration.exists());
+    assert!(!migrations_dir.join("00001-foo").exists());
+
+    let output = run(&["status"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Asset directories left behind by older versions are skipped too
+    fs::create_dir_all(migrations_dir.join("00001-foo")).unwrap();
+    fs::write(migrations_dir.join("00001-foo/config.json"), "{}\n").unwrap();
+    let output = run(&["status"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}